@@ -1,37 +1,67 @@
 use regex::Regex;
 use std::fs::read_to_string;
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Button {
+    step: (i64, i64),
+    cost: i64,
+    limit: Option<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 struct Game {
-    button_a: (i64, i64),
-    button_b: (i64, i64),
+    buttons: Vec<Button>,
     prize: (i64, i64),
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Solution {
+    cost: i64,
+    presses: Vec<i64>,
+}
+
 const COST_BUTTON_A: i64 = 3;
 const COST_BUTTON_B: i64 = 1;
 
+fn default_cost(label: &str) -> Option<i64> {
+    match label {
+        "A" => Some(COST_BUTTON_A),
+        "B" => Some(COST_BUTTON_B),
+        _ => None,
+    }
+}
+
+/// Buttons may carry `, Cost=N` and `, Limit=N` (e.g. `Button C: X+10, Y+10,
+/// Cost=2, Limit=5`), the cost can only be omitted for buttons A and B.
 fn read_data(path: &str) -> std::io::Result<Vec<Game>> {
-    let regex_button = Regex::new(r"Button [AB]: X\+(?<x>\d+), Y\+(?<y>\d+)").unwrap();
+    let regex_button = Regex::new(
+        r"Button (?<label>\w+): X\+(?<x>\d+), Y\+(?<y>\d+)(, Cost=(?<cost>\d+))?(, Limit=(?<limit>\d+))?",
+    )
+    .unwrap();
     let regex_prize = Regex::new(r"Prize: X=(?<x>\d+), Y=(?<y>\d+)").unwrap();
 
     let mut games = Vec::new();
     let mut next_game = Game::default();
     for line in read_to_string(path)?.split("\n") {
-        if line.starts_with("Button A:") {
-            let caps = regex_button.captures(line).unwrap();
-
-            let x = caps["x"].parse::<i64>().unwrap();
-            let y = caps["y"].parse::<i64>().unwrap();
-
-            next_game.button_a = (x, y);
-        } else if line.starts_with("Button B:") {
+        if line.starts_with("Button ") {
             let caps = regex_button.captures(line).unwrap();
 
             let x = caps["x"].parse::<i64>().unwrap();
             let y = caps["y"].parse::<i64>().unwrap();
-
-            next_game.button_b = (x, y);
+            let cost = caps
+                .name("cost")
+                .map(|cost| cost.as_str().parse::<i64>().unwrap())
+                .or_else(|| default_cost(&caps["label"]))
+                .expect("Missing button cost");
+            let limit = caps
+                .name("limit")
+                .map(|limit| limit.as_str().parse::<i64>().unwrap());
+
+            next_game.buttons.push(Button {
+                step: (x, y),
+                cost,
+                limit,
+            });
         } else if line.starts_with("Prize: ") {
             let caps = regex_prize.captures(line).unwrap();
 
@@ -40,24 +70,219 @@ fn read_data(path: &str) -> std::io::Result<Vec<Game>> {
 
             next_game.prize = (x, y);
 
-            games.push(next_game);
-            next_game = Game::default();
+            games.push(std::mem::take(&mut next_game));
         }
     }
     Ok(games)
 }
 
+const PART_1_PRESS_LIMIT: i64 = 100;
 const PART_2_OFFSET: i64 = 10000000000000;
 
 const PART_1: bool = false;
 const PART_2: bool = true;
 
-fn solve2(game: Game, is_part_2: bool) -> i64 {
-    let (prize_x, prize_y) = if is_part_2 {
-        (game.prize.0 + PART_2_OFFSET, game.prize.1 + PART_2_OFFSET)
+/// Upper bound on the number of press combinations `find_presses` tries for a
+/// single machine before giving up.
+const MAX_SEARCH_STEPS: i128 = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SearchError {
+    TooManyCombinations { buttons: usize, combinations: i128 },
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::TooManyCombinations {
+                buttons,
+                combinations,
+            } => write!(
+                f,
+                "machine with {buttons} buttons needs {combinations} press combinations, \
+                 more than {MAX_SEARCH_STEPS}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<SearchError> for std::io::Error {
+    fn from(err: SearchError) -> Self {
+        std::io::Error::other(err)
+    }
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
     } else {
-        game.prize
-    };
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    x: i128,
+    y: i128,
+    cost: i128,
+    limit: i128,
+}
+
+impl Step {
+    fn new(button: &Button, limit: Option<i64>) -> Self {
+        let limit = match (button.limit, limit) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b).unwrap_or(i64::MAX),
+        };
+        Step {
+            x: button.step.0 as i128,
+            y: button.step.1 as i128,
+            cost: button.cost as i128,
+            limit: limit as i128,
+        }
+    }
+
+    fn max_presses(&self, prize: (i128, i128)) -> i128 {
+        let mut bound = self.limit;
+        if self.x > 0 {
+            bound = bound.min(prize.0 / self.x);
+        }
+        if self.y > 0 {
+            bound = bound.min(prize.1 / self.y);
+        }
+        if self.x == 0 && self.y == 0 {
+            // Pressing the button does not move the claw at all.
+            bound = 0;
+        }
+        bound.max(0)
+    }
+
+    fn cross(&self, other: &Step) -> i128 {
+        self.x * other.y - self.y * other.x
+    }
+}
+
+fn ceil_div(a: i128, b: i128) -> i128 {
+    a.div_euclid(b) + (a.rem_euclid(b) != 0) as i128
+}
+
+/// Finds the cheapest `(t1, t2)` with `c1 * t1 + c2 * t2 == p` and
+/// `0 <= t1 <= s1.limit`, `0 <= t2 <= s2.limit`.
+///
+/// Assumes `c1, c2 >= 0`.
+fn solve_diophantine(c1: i128, c2: i128, p: i128, s1: &Step, s2: &Step) -> Option<(i128, i128)> {
+    let cost = |(t1, t2): (i128, i128)| s1.cost * t1 + s2.cost * t2;
+    let in_limits = |&(t1, t2): &(i128, i128)| t1 <= s1.limit && t2 <= s2.limit;
+
+    match (c1, c2) {
+        (0, 0) => (p == 0).then_some((0, 0)),
+        (0, _) => (p >= 0 && p % c2 == 0)
+            .then(|| (0, p / c2))
+            .filter(in_limits),
+        (_, 0) => (p >= 0 && p % c1 == 0)
+            .then(|| (p / c1, 0))
+            .filter(in_limits),
+        _ => {
+            let (g, x, y) = extended_gcd(c1, c2);
+            if p % g != 0 {
+                return None;
+            }
+
+            // All solutions: t1 = t1_0 + k * d1, t2 = t2_0 - k * d2
+            let (t1_0, t2_0) = (x * (p / g), y * (p / g));
+            let (d1, d2) = (c2 / g, c1 / g);
+
+            // t1 >= 0         <=> k >= ceil(-t1_0 / d1)
+            // t2 <= s2.limit  <=> k >= ceil((t2_0 - s2.limit) / d2)
+            // t2 >= 0         <=> k <= floor(t2_0 / d2)
+            // t1 <= s1.limit  <=> k <= floor((s1.limit - t1_0) / d1)
+            let k_min = ceil_div(-t1_0, d1).max(ceil_div(t2_0 - s2.limit, d2));
+            let k_max = t2_0.div_euclid(d2).min((s1.limit - t1_0).div_euclid(d1));
+            if k_min > k_max {
+                return None;
+            }
+
+            // The cost is linear in k, so the optimum is at one of the two ends.
+            [k_min, k_max]
+                .into_iter()
+                .map(|k| (t1_0 + k * d1, t2_0 - k * d2))
+                .min_by_key(|&t| cost(t))
+        }
+    }
+}
+
+/// Unique integral solution of a non-collinear pair, ignoring signs and limits.
+fn solve_cramer(s1: &Step, s2: &Step, prize: (i128, i128)) -> Option<(i128, i128)> {
+    let (p1, p2) = prize;
+    let det = s1.cross(s2);
+    let det1 = p1 * s2.y - p2 * s2.x;
+    let det2 = s1.x * p2 - s1.y * p1;
+    if det1 % det != 0 || det2 % det != 0 {
+        return None;
+    }
+    Some((det1 / det, det2 / det))
+}
+
+/// Range of `k` with `0 <= a - k * d <= max`, empty if `lo > hi`.
+fn press_range(a: i128, d: i128, max: i128) -> (i128, i128) {
+    match d.signum() {
+        0 if (0..=max).contains(&a) => (i128::MIN, i128::MAX),
+        0 => (1, 0),
+        1 => (ceil_div(a - max, d), a.div_euclid(d)),
+        _ => (ceil_div(-a, -d), (max - a).div_euclid(-d)),
+    }
+}
+
+/// Solves `step * t + s1 * t1 + s2 * t2 == prize` for a pair `s1`, `s2` which
+/// is not collinear.
+///
+/// The pair only has an integral solution for `t` in fixed residues modulo
+/// `|det|`. Within a residue class `t1`, `t2` and the cost are linear in the
+/// number of periods, so only both ends of the feasible range are checked.
+fn find_presses_triple(
+    step: &Step,
+    s1: &Step,
+    s2: &Step,
+    prize: (i128, i128),
+) -> Option<(i128, i128, i128)> {
+    let det = s1.cross(s2);
+    let period = det.abs();
+    let max_t = step.max_presses(prize);
+
+    // Change of t1 and t2 when pressing `step` another `period` times
+    let d1 = det.signum() * step.cross(s2);
+    let d2 = det.signum() * s1.cross(step);
+
+    let cost = |(t, t1, t2): (i128, i128, i128)| step.cost * t + s1.cost * t1 + s2.cost * t2;
+
+    (0..=max_t.min(period - 1))
+        .filter_map(|r| {
+            let remaining = (prize.0 - step.x * r, prize.1 - step.y * r);
+            let (t1, t2) = solve_cramer(s1, s2, remaining)?;
+
+            let (lo1, hi1) = press_range(t1, d1, s1.limit);
+            let (lo2, hi2) = press_range(t2, d2, s2.limit);
+            let k_min = lo1.max(lo2).max(0);
+            let k_max = hi1.min(hi2).min((max_t - r) / period);
+            if k_min > k_max {
+                return None;
+            }
+
+            [k_min, k_max]
+                .into_iter()
+                .map(|k| (r + k * period, t1 - k * d1, t2 - k * d2))
+                .min_by_key(|&presses| cost(presses))
+        })
+        .min_by_key(|&presses| cost(presses))
+}
+
+fn find_presses_pair(s1: &Step, s2: &Step, prize: (i128, i128)) -> Option<(i128, i128)> {
+    let (a1, a2) = (s1.x, s1.y);
+    let (b1, b2) = (s2.x, s2.y);
+    let (p1, p2) = prize;
 
     // Solve for [t1, t2]:
     //
@@ -65,71 +290,192 @@ fn solve2(game: Game, is_part_2: bool) -> i64 {
     // |    | = |           | * |    |
     // | p2 |   | a2    b2  |   | t2 |
 
-    let mut equation_system = [
-        [game.button_a.0 as f64, game.button_b.0 as f64],
-        [game.button_a.1 as f64, game.button_b.1 as f64],
-    ];
-    let mut target = [prize_x as f64, prize_y as f64];
-
-    // 1) II - a2/a1 * I
-    if equation_system[0][0] == 0.0 {
-        // No solution possible in this case.
-        return 0;
+    if s1.cross(s2) != 0 {
+        let (t1, t2) = solve_cramer(s1, s2, prize)?;
+        return ((0..=s1.limit).contains(&t1) && (0..=s2.limit).contains(&t2)).then_some((t1, t2));
     }
-    let m = equation_system[1][0] / equation_system[0][0];
-    equation_system[1][0] -= m * equation_system[0][0];
-    equation_system[1][1] -= m * equation_system[0][1];
-    target[1] -= m * target[0];
 
-    if equation_system[1][1] == 0.0 {
-        // No solution
-        return 0;
+    // Both buttons are collinear, so the prize has to lie on the same line.
+    if a1 * p2 != a2 * p1 || b1 * p2 != b2 * p1 {
+        return None;
     }
 
-    // 2) t2 = p2 / (b2 - a2/a1 * b1)
-    let t2 = target[1] / equation_system[1][1];
+    // Both rows describe the same constraint, pick the one which is not trivial.
+    let presses = if a1 != 0 || b1 != 0 {
+        solve_diophantine(a1, b1, p1, s1, s2)?
+    } else {
+        solve_diophantine(a2, b2, p2, s1, s2)?
+    };
 
-    equation_system[1][1] = 1.0;
-    target[1] = t2;
+    // Check correctness of result
+    let (t1, t2) = presses;
+    (a1 * t1 + b1 * t2 == p1 && a2 * t1 + b2 * t2 == p2).then_some(presses)
+}
 
-    // 3) I - b1 * II
-    target[0] -= equation_system[0][1] * t2;
-    equation_system[0][1] = 0.0;
+fn update_best(best: &mut Option<(i128, Vec<i128>)>, steps: &[Step], presses: &[i128]) {
+    let cost = presses
+        .iter()
+        .zip(steps)
+        .map(|(t, step)| t * step.cost)
+        .sum::<i128>();
+    if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+        *best = Some((cost, presses.to_vec()));
+    }
+}
 
-    // t1 = (p1 - b1 * t2) / a1
-    let t1 = target[0] / equation_system[0][0];
+fn search(
+    steps: &[Step],
+    order: &[usize],
+    prize: (i128, i128),
+    presses: &mut [i128],
+    best: &mut Option<(i128, Vec<i128>)>,
+) {
+    match *order {
+        [] => {
+            if prize == (0, 0) {
+                update_best(best, steps, presses);
+            }
+        }
+        [idx] => {
+            let step = &steps[idx];
+            let t = step.max_presses(prize);
+            if (step.x * t, step.y * t) == prize {
+                presses[idx] = t;
+                update_best(best, steps, presses);
+                presses[idx] = 0;
+            }
+        }
+        [idx1, idx2] => {
+            if let Some((t1, t2)) = find_presses_pair(&steps[idx1], &steps[idx2], prize) {
+                presses[idx1] = t1;
+                presses[idx2] = t2;
+                update_best(best, steps, presses);
+                presses[idx1] = 0;
+                presses[idx2] = 0;
+            }
+        }
+        [idx, idx1, idx2] if steps[idx1].cross(&steps[idx2]) != 0 => {
+            let (s1, s2) = (&steps[idx1], &steps[idx2]);
+            if let Some((t, t1, t2)) = find_presses_triple(&steps[idx], s1, s2, prize) {
+                presses[idx] = t;
+                presses[idx1] = t1;
+                presses[idx2] = t2;
+                update_best(best, steps, presses);
+                presses[idx] = 0;
+                presses[idx1] = 0;
+                presses[idx2] = 0;
+            }
+        }
+        [idx, ref rest @ ..] => {
+            let step = &steps[idx];
+            for t in 0..=step.max_presses(prize) {
+                presses[idx] = t;
+                let remaining = (prize.0 - step.x * t, prize.1 - step.y * t);
+                search(steps, rest, remaining, presses, best);
+            }
+            presses[idx] = 0;
+        }
+    }
+}
 
-    equation_system[0][0] = 1.0;
-    target[0] = t1;
+/// Number of press combinations `search` tries for `order`.
+fn search_size(steps: &[Step], order: &[usize], prize: (i128, i128)) -> i128 {
+    let n = order.len();
+    if n <= 2 {
+        return 1;
+    }
+    let det = steps[order[n - 2]].cross(&steps[order[n - 1]]);
+    let (enumerated, inner) = if det != 0 {
+        let extra = steps[order[n - 3]].max_presses(prize) + 1;
+        (&order[..n - 3], extra.min(det.abs()))
+    } else {
+        (&order[..n - 2], 1)
+    };
+    enumerated.iter().fold(inner, |size, &idx| {
+        size.saturating_mul(steps[idx].max_presses(prize) + 1)
+    })
+}
 
-    for (t1, t2) in [
-        (t1.ceil() as i64, t2.ceil() as i64),
-        (t1.floor() as i64, t2.ceil() as i64),
-        (t1.ceil() as i64, t2.floor() as i64),
-        (t1.floor() as i64, t2.floor() as i64),
-    ] {
-        // Check correctness of result
-        if game.button_a.0 * t1 + game.button_b.0 * t2 == prize_x
-            && game.button_a.1 * t1 + game.button_b.1 * t2 == prize_y
+/// Two buttons are solved in closed form and a third one by its residues
+/// modulo the determinant of the pair. Every button beyond that is enumerated
+/// up to its press bound, which fails once there are too many combinations.
+fn find_presses(
+    game: &Game,
+    prize: (i64, i64),
+    limit: Option<i64>,
+) -> Result<Option<Solution>, SearchError> {
+    let steps: Vec<Step> = game
+        .buttons
+        .iter()
+        .map(|button| Step::new(button, limit))
+        .collect();
+    let prize = (prize.0 as i128, prize.1 as i128);
+
+    // Enumerate the buttons with the fewest possible presses, the two with the
+    // most are solved exactly.
+    let mut order: Vec<usize> = (0..steps.len()).collect();
+    order.sort_by_key(|&idx| steps[idx].max_presses(prize));
+
+    // A third button can only be skipped by periods if the pair is not collinear.
+    if let Some(&last) = order.last() {
+        let n = order.len();
+        if let Some(pos) = (0..n - 1)
+            .rev()
+            .find(|&pos| steps[order[pos]].cross(&steps[last]) != 0)
         {
-            return COST_BUTTON_A * t1 + COST_BUTTON_B * t2;
+            order.swap(pos, n - 2);
         }
     }
 
-    0
+    let combinations = search_size(&steps, &order, prize);
+    if combinations > MAX_SEARCH_STEPS {
+        return Err(SearchError::TooManyCombinations {
+            buttons: steps.len(),
+            combinations,
+        });
+    }
+
+    let mut presses = vec![0; steps.len()];
+    let mut best = None;
+    search(&steps, &order, prize, &mut presses, &mut best);
+
+    Ok(best.map(|(cost, presses)| Solution {
+        cost: cost as i64,
+        presses: presses.into_iter().map(|t| t as i64).collect(),
+    }))
+}
+
+fn solve(game: &Game, is_part_2: bool) -> Result<i64, SearchError> {
+    let (prize, limit) = if is_part_2 {
+        (
+            (game.prize.0 + PART_2_OFFSET, game.prize.1 + PART_2_OFFSET),
+            None,
+        )
+    } else {
+        (game.prize, Some(PART_1_PRESS_LIMIT))
+    };
+
+    Ok(find_presses(game, prize, limit)?.map_or(0, |solution| solution.cost))
 }
 
 fn main() -> std::io::Result<()> {
     // let data = read_data("example.txt")?;
+    // let data = read_data("example2.txt")?;
     let data = read_data("input.txt")?;
 
     let start = std::time::Instant::now();
-    let task1 = data.iter().map(|&game| solve2(game, PART_1)).sum::<i64>();
+    let task1 = data
+        .iter()
+        .map(|game| solve(game, PART_1))
+        .sum::<Result<i64, _>>()?;
     let duration = start.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start = std::time::Instant::now();
-    let task2 = data.iter().map(|&game| solve2(game, PART_2)).sum::<i64>();
+    let task2 = data
+        .iter()
+        .map(|game| solve(game, PART_2))
+        .sum::<Result<i64, _>>()?;
     let duration = start.elapsed();
     println!("Task 2: {task2} (took {:?})", duration);
 
@@ -164,27 +510,66 @@ Find all solutions:
 | p2 |   | a2    b2  |   | t2 |
 
 
-| a1    b1 ||  p1 |
-| a2    b2 ||  p2 |
+1. det = a1 * b2 - a2 * b1 != 0 (Cramer's rule)
+
+t1 = (p1 * b2 - p2 * b1) / det
+t2 = (a1 * p2 - a2 * p1) / det
 
-1. II - a2/a1 * I
+=> Valid iff both divisions are exact and t1, t2 >= 0.
 
-| a1    b1              || p1  |
-| 0     b2 - a2/a1 * b1 || p2  |
+2. det == 0 (buttons are collinear)
 
-2. t2 = p2 / (b2 - a2/a1 * b1)
+Both rows are multiples of each other (if the prize lies on the line at all),
+so only a1 * t1 + b1 * t2 = p1 is left. With g = gcd(a1, b1) and
+a1 * x + b1 * y = g (extended Euclid):
 
-| a1    b1  || p1  |
-| 0     1   || t2  |
+t1 = x * p1 / g + k * b1 / g
+t2 = y * p1 / g - k * a1 / g
 
-3. I - b1 * II
+t1, t2 >= 0 restricts k to an interval and 3 * t1 + t2 is linear in k,
+so the cheapest solution is at one of the interval bounds. Press limits
+only shrink the interval further.
 
-| a1    0   || p1 - b1 * t2  |
-| 0     1   || t2            |
+3. Three buttons
 
-4. t1 = (p1 - b1 * t2) / a1
+With a non-collinear pair (det != 0) and t presses of the third button c,
+Cramer's rule gives t1(t), t2(t). Their numerators change by det(c, b) * |det|
+and det(a, c) * |det| when t grows by |det|, so integrality only depends on
+t mod |det|. Within a residue class t1, t2 and the cost are linear in the
+number of periods k, so again only the bounds of the k interval matter.
 
-| 1     0   || t1 |
-| 0     1   || t2 |
+4. More than three buttons
+
+Fix the presses of all remaining buttons (each bounded by its limit and by
+prize / step) and solve the rest as above. This is only feasible for small
+bounds, so the search gives up beyond MAX_SEARCH_STEPS combinations.
 
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(path: &str, part_1: i64, part_2: i64) {
+        let games = read_data(path).unwrap();
+        let total = |is_part_2| {
+            games
+                .iter()
+                .map(|game| solve(game, is_part_2))
+                .sum::<Result<i64, _>>()
+                .unwrap()
+        };
+        assert_eq!(total(PART_1), part_1);
+        assert_eq!(total(PART_2), part_2);
+    }
+
+    #[test]
+    fn example() {
+        check("example.txt", 480, 875318608908);
+    }
+
+    #[test]
+    fn example2() {
+        check("example2.txt", 280, 351351351633);
+    }
+}
@@ -1,5 +1,7 @@
 use regex::Regex;
-use std::fs::read_to_string;
+use std::fs::{self, read_to_string};
+use std::ops::Range;
+use std::path::Path;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 struct Robot {
@@ -7,11 +9,29 @@ struct Robot {
     velocity: (i32, i32),
 }
 
-fn read_data(path: &str) -> std::io::Result<Vec<Robot>> {
+const DEFAULT_SIZE: (i32, i32) = (101, 103);
+
+/// An optional first line `size=<width>,<height>` overrides `DEFAULT_SIZE`.
+fn read_data(path: &str) -> std::io::Result<(Vec<Robot>, (i32, i32))> {
     let re = Regex::new(r"p=(?<px>\d+),(?<py>\d+) v=(?<vx>-?\d+),(?<vy>-?\d+)").unwrap();
+    let re_size = Regex::new(r"^size=(?<width>\d+),(?<height>\d+)$").unwrap();
+
+    let input = read_to_string(path)?;
+    let mut rows = input.split("\n").peekable();
+
+    let size = match rows.peek().and_then(|row| re_size.captures(row)) {
+        Some(caps) => {
+            let size = (
+                caps["width"].parse::<i32>().unwrap(),
+                caps["height"].parse::<i32>().unwrap(),
+            );
+            rows.next();
+            size
+        }
+        None => DEFAULT_SIZE,
+    };
 
-    Ok(read_to_string(path)?
-        .split("\n")
+    let robots = rows
         .map(|row| {
             let caps = re.captures(row).unwrap();
             Robot {
@@ -25,112 +45,206 @@ fn read_data(path: &str) -> std::io::Result<Vec<Robot>> {
                 ),
             }
         })
-        .collect())
+        .collect();
+
+    Ok((robots, size))
 }
 
-fn quadrant_product(moved_robots: Vec<(i32, i32)>, width: i32, height: i32) -> i32 {
-    let mid_width = width / 2;
-    let mid_height = height / 2;
+/// Assignment of tiles to regions, tiles mapped to `None` are not counted.
+struct Partition {
+    regions: usize,
+    region_of: Box<dyn Fn(i32, i32) -> Option<usize>>,
+}
 
-    let mut quadrants: [i32; 4] = [0, 0, 0, 0];
-    for (x, y) in moved_robots {
-        // 1st quadrant?
-        if x < mid_width && y < mid_height {
-            quadrants[0] += 1;
-            continue;
+impl Partition {
+    fn new(regions: usize, region_of: impl Fn(i32, i32) -> Option<usize> + 'static) -> Self {
+        Partition {
+            regions,
+            region_of: Box::new(region_of),
         }
+    }
 
-        // 2nd quadrant
-        if x > mid_width && y < mid_height {
-            quadrants[1] += 1;
-            continue;
-        }
+    /// Splits the field into `columns` x `rows` equally sized regions,
+    /// numbered row by row. Tiles which straddle a region border (e.g. the
+    /// middle row/column of an odd-sized field split in two) belong to no
+    /// region.
+    fn grid(columns: usize, rows: usize, width: i32, height: i32) -> Self {
+        let band = |pos: i32, size: i32, parts: usize| -> Option<usize> {
+            let (pos, size, parts) = (pos as usize, size as usize, parts);
+            let idx = pos * parts / size;
+            ((pos + 1) * parts <= (idx + 1) * size).then_some(idx)
+        };
+
+        Partition::new(columns * rows, move |x, y| {
+            Some(band(y, height, rows)? * columns + band(x, width, columns)?)
+        })
+    }
 
-        // 3rd quadrant
-        if x < mid_width && y > mid_height {
-            quadrants[2] += 1;
-            continue;
-        }
+    fn quadrants(width: i32, height: i32) -> Self {
+        Partition::grid(2, 2, width, height)
+    }
+}
 
-        // 4th quadrant
-        if x > mid_width && y > mid_height {
-            quadrants[3] += 1;
+fn region_counts(positions: &[(i32, i32)], partition: &Partition) -> Vec<u64> {
+    let mut counts = vec![0; partition.regions];
+    for &(x, y) in positions {
+        if let Some(region) = (partition.region_of)(x, y) {
+            counts[region] += 1;
         }
     }
+    counts
+}
 
-    quadrants.into_iter().product()
+fn safety_factor(positions: &[(i32, i32)], partition: &Partition) -> u64 {
+    region_counts(positions, partition).into_iter().product()
 }
 
-fn solve(
-    robots: &Vec<Robot>,
+fn density_map(
+    positions: &[(i32, i32)],
     width: i32,
     height: i32,
-    seconds: i32,
-    print: bool,
-) -> Vec<(i32, i32)> {
-    assert!(seconds > 0);
+    block_width: i32,
+    block_height: i32,
+) -> Vec<Vec<u32>> {
+    let columns = (width + block_width - 1) / block_width;
+    let rows = (height + block_height - 1) / block_height;
+
+    let mut density = vec![vec![0; columns as usize]; rows as usize];
+    for &(x, y) in positions {
+        density[(y / block_height) as usize][(x / block_width) as usize] += 1;
+    }
+    density
+}
 
-    let mut field = vec![vec![0; width as usize]; height as usize];
+fn render(positions: &[(i32, i32)], width: i32, height: i32) -> String {
+    let mut frame = String::new();
+    for row in density_map(positions, width, height, 1, 1) {
+        for count in row {
+            if count > 0 {
+                frame.push_str(&format!(" {} ", count));
+            } else {
+                frame.push_str("   ");
+            }
+        }
+        frame.push('\n');
+    }
+    frame
+}
 
-    let mut moved_robots: Vec<(i32, i32)> = robots
-        .into_iter()
+fn positions_at(robots: &[Robot], width: i32, height: i32, seconds: i32) -> Vec<(i32, i32)> {
+    // Reduce first to avoid overflows for large times.
+    let (t_x, t_y) = (seconds.rem_euclid(width), seconds.rem_euclid(height));
+    robots
+        .iter()
         .map(|robot| {
             (
-                (robot.pos.0 + robot.velocity.0).rem_euclid(width),
-                (robot.pos.1 + robot.velocity.1).rem_euclid(height),
+                (robot.pos.0 + robot.velocity.0 * t_x).rem_euclid(width),
+                (robot.pos.1 + robot.velocity.1 * t_y).rem_euclid(height),
             )
         })
-        .map(|(x, y)| {
-            field[y as usize][x as usize] += 1;
-            (x, y)
-        })
-        .collect();
-
-    for _ in 2..=seconds {
-        // Update robot positions
-        for robot_id in 0..moved_robots.len() {
-            let (x, y) = moved_robots[robot_id];
-            field[y as usize][x as usize] -= 1;
+        .collect()
+}
 
-            let (x, y) = (
-                (x + robots[robot_id].velocity.0).rem_euclid(width),
-                (y + robots[robot_id].velocity.1).rem_euclid(height),
-            );
-            moved_robots[robot_id] = (x, y);
-            field[y as usize][x as usize] += 1;
+fn export_frames(
+    robots: &[Robot],
+    width: i32,
+    height: i32,
+    seconds: Range<i32>,
+    dir: &Path,
+) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for t in seconds {
+        let mut occupied = vec![vec![false; width as usize]; height as usize];
+        for (x, y) in positions_at(robots, width, height, t) {
+            occupied[y as usize][x as usize] = true;
         }
-    }
 
-    if print {
-        for idx in 0..field.len() {
-            for idx2 in 0..field[idx].len() {
-                if field[idx][idx2] > 0 {
-                    print!(" {} ", field[idx][idx2]);
-                } else {
-                    print!("   ");
-                }
-            }
-            println!("");
+        let mut image = format!("P1\n# second {t}\n{width} {height}\n");
+        for row in occupied {
+            let pixels: Vec<&str> = row
+                .into_iter()
+                .map(|occupied| if occupied { "1" } else { "0" })
+                .collect();
+            image.push_str(&pixels.join(" "));
+            image.push('\n');
         }
+
+        fs::write(dir.join(format!("frame_{t:05}.pbm")), image)?;
     }
 
-    moved_robots
+    Ok(())
+}
+
+/// `n^2` times the variance of the values.
+fn scaled_variance(values: impl Iterator<Item = i64>) -> i64 {
+    let (n, sum, sum_sq) = values.fold((0, 0, 0), |(n, sum, sum_sq), v| {
+        (n + 1, sum + v, sum_sq + v * v)
+    });
+    n * sum_sq - sum * sum
+}
+
+fn most_clustered_second(robots: &[Robot], axis: impl Fn(&Robot) -> (i32, i32), size: i32) -> i32 {
+    (0..size)
+        .min_by_key(|&t| {
+            scaled_variance(robots.iter().map(|robot| {
+                let (p, v) = axis(robot);
+                (p + v * t).rem_euclid(size) as i64
+            }))
+        })
+        .unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EasterEgg {
+    second: i32,
+    frame: String,
+}
+
+/// The x positions repeat every `width` seconds and the y positions every
+/// `height` seconds, so the picture is found independently per axis as the
+/// second with the lowest position variance. Both are combined via the Chinese
+/// remainder theorem.
+fn find_easter_egg(robots: &[Robot], width: i32, height: i32) -> Option<EasterEgg> {
+    let t_x = most_clustered_second(robots, |robot| (robot.pos.0, robot.velocity.0), width);
+    let t_y = most_clustered_second(robots, |robot| (robot.pos.1, robot.velocity.1), height);
+
+    // t = t_x (mod width) and t = t_y (mod height)
+    let second = (0..height)
+        .map(|k| t_x + k * width)
+        .find(|t| t % height == t_y)?;
+
+    Some(EasterEgg {
+        second,
+        frame: render(&positions_at(robots, width, height, second), width, height),
+    })
 }
 
 fn main() -> std::io::Result<()> {
-    // let (data, width, height) = (read_data("example.txt")?, 11, 7);
-    let (data, width, height) = (read_data("input.txt")?, 101, 103);
+    // let (data, (width, height)) = read_data("example.txt")?;
+    let (data, (width, height)) = read_data("input.txt")?;
 
     let start = std::time::Instant::now();
-    let task1 = quadrant_product(solve(&data, width, height, 100, false), width, height);
+    let task1 = safety_factor(
+        &positions_at(&data, width, height, 100),
+        &Partition::quadrants(width, height),
+    );
     let duration = start.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start = std::time::Instant::now();
-    let task2 = 6532;
-    solve(&data, width, height, task2, true);
+    let easter_egg = find_easter_egg(&data, width, height).expect("No Easter egg found");
     let duration = start.elapsed();
-    println!("Task 2 (took {:?})", duration);
+    print!("{}", easter_egg.frame);
+    println!("Task 2: {} (took {:?})", easter_egg.second, duration);
+
+    // `cargo run -- frames <dir>` dumps all frames of one period
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {}
+        ["frames", dir] => export_frames(&data, width, height, 0..width * height, Path::new(dir))?,
+        _ => panic!("Expected `frames <dir>`"),
+    }
 
     Ok(())
 }
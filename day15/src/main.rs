@@ -1,4 +1,16 @@
-use std::{collections::HashSet, fs::read_to_string};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    terminal::{self, ClearType},
+};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::read_to_string,
+    io::{stdout, Write},
+    rc::Rc,
+};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
@@ -8,6 +20,8 @@ enum Element {
     Wall,
     Empty,
     BoxLeft,
+    /// Inside of a box wider than two tiles, e.g. `[==]`.
+    BoxInner,
     BoxRight,
 }
 
@@ -25,7 +39,7 @@ fn read_data(path: &str) -> std::io::Result<(Vec<Vec<Element>>, Vec<Move>)> {
 
     let mut field = Vec::new();
     let mut iter = data.split("\n");
-    while let Some(line) = iter.next() {
+    for line in iter.by_ref() {
         if line.is_empty() {
             break;
         }
@@ -37,6 +51,9 @@ fn read_data(path: &str) -> std::io::Result<(Vec<Vec<Element>>, Vec<Move>)> {
                     '@' => Element::Robot,
                     '.' => Element::Empty,
                     'O' => Element::Box,
+                    '[' => Element::BoxLeft,
+                    '=' => Element::BoxInner,
+                    ']' => Element::BoxRight,
                     _ => panic!("Invalid field element"),
                 })
                 .collect::<Vec<Element>>(),
@@ -44,7 +61,7 @@ fn read_data(path: &str) -> std::io::Result<(Vec<Vec<Element>>, Vec<Move>)> {
     }
 
     let mut moves = Vec::new();
-    while let Some(line) = iter.next() {
+    for line in iter {
         moves.extend(line.chars().map(|c| match c {
             '<' => Move::Left,
             '^' => Move::Up,
@@ -57,254 +74,478 @@ fn read_data(path: &str) -> std::io::Result<(Vec<Vec<Element>>, Vec<Move>)> {
     Ok((field, moves))
 }
 
-fn solve(mut field: Vec<Vec<Element>>, moves: &Vec<Move>) -> usize {
-    let mut robot_pos_opt = None;
-    for row in 0..field.len() {
-        for col in 0..field[row].len() {
-            if field[row][col] == Element::Robot {
-                robot_pos_opt = Some((row, col));
-                break;
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+struct WarehouseBox {
+    row: usize,
+    col: usize,
+    width: usize,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Tile {
+    Wall,
+    Empty,
+    Box(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Warehouse {
+    tiles: Vec<Vec<Tile>>,
+    boxes: Vec<WarehouseBox>,
+    robot: (usize, usize),
+}
+
+impl Move {
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Move::Left => (0, -1),
+            Move::Right => (0, 1),
+            Move::Up => (-1, 0),
+            Move::Down => (1, 0),
+        }
+    }
+}
+
+impl Warehouse {
+    /// Every tile is stretched to `scale` tiles horizontally, except for the
+    /// robot, i.e. `scale = 1` is part 1 and `scale = 2` part 2.
+    fn new(field: &[Vec<Element>], scale: usize) -> Self {
+        assert!(scale > 0);
+
+        let mut tiles = vec![vec![Tile::Empty; field[0].len() * scale]; field.len()];
+        let mut boxes = Vec::new();
+        let mut robot = None;
+
+        for (row, elements) in field.iter().enumerate() {
+            for (col, element) in elements.iter().enumerate() {
+                let width = match element {
+                    Element::Empty | Element::BoxInner | Element::BoxRight => continue,
+                    Element::Robot => {
+                        assert!(robot.is_none(), "Multiple robots detected!");
+                        robot = Some((row, col * scale));
+                        continue;
+                    }
+                    Element::Wall => {
+                        tiles[row][col * scale..(col + 1) * scale].fill(Tile::Wall);
+                        continue;
+                    }
+                    Element::Box => scale,
+                    Element::BoxLeft => {
+                        let right = (col + 1..elements.len())
+                            .find(|&col| elements[col] == Element::BoxRight)
+                            .expect("Unterminated box");
+                        (right - col + 1) * scale
+                    }
+                };
+
+                tiles[row][col * scale..col * scale + width].fill(Tile::Box(boxes.len()));
+                boxes.push(WarehouseBox {
+                    row,
+                    col: col * scale,
+                    width,
+                });
             }
         }
 
-        if robot_pos_opt.is_some() {
-            break;
+        Warehouse {
+            tiles,
+            boxes,
+            robot: robot.expect("No robot found"),
         }
     }
 
-    let (mut x, mut y) = robot_pos_opt.unwrap();
-
-    let move_robot = |field: &mut Vec<Vec<Element>>,
-                      old_x: usize,
-                      old_y: usize,
-                      new_x: usize,
-                      new_y: usize|
-     -> (usize, usize) {
-        field[old_x][old_y] = Element::Empty;
-        field[new_x][new_y] = Element::Robot;
-        (new_x, new_y)
-    };
-
-    let move_box =
-        |field: &mut Vec<Vec<Element>>, old_x: usize, old_y: usize, new_x: usize, new_y: usize| {
-            field[old_x][old_y] = Element::Empty;
-            field[new_x][new_y] = Element::Box;
+    /// Moves the robot and pushes all boxes in its way. Returns the number of
+    /// pushed boxes or `None` if the move is blocked by a wall.
+    fn step(&mut self, robot_move: Move) -> Option<usize> {
+        let (ox, oy) = robot_move.offset();
+        let shift = |(x, y): (usize, usize)| {
+            (
+                x.checked_add_signed(ox).unwrap(),
+                y.checked_add_signed(oy).unwrap(),
+            )
         };
 
-    for &robot_move in moves {
-        let (ox, oy) = match robot_move {
-            Move::Left => (0, -1),
-            Move::Right => (0, 1),
-            Move::Up => (-1, 0),
-            Move::Down => (1, 0),
+        // BFS over all boxes affected by the push
+        let mut boxes = Vec::new();
+        let mut visited = HashSet::new();
+        let mut frontier = vec![shift(self.robot)];
+        let mut next_idx = 0;
+        loop {
+            for (x, y) in frontier.drain(..) {
+                match self.tiles[x][y] {
+                    Tile::Wall => return None,
+                    Tile::Empty => {}
+                    Tile::Box(id) => {
+                        if visited.insert(id) {
+                            boxes.push(id);
+                        }
+                    }
+                }
+            }
+
+            let Some(&id) = boxes.get(next_idx) else {
+                break;
+            };
+            next_idx += 1;
+
+            // Tiles the box moves into which it does not already cover
+            let WarehouseBox { row, col, width } = self.boxes[id];
+            frontier.extend(
+                (col..col + width)
+                    .map(|y| shift((row, y)))
+                    .filter(|&(x, y)| self.tiles[x][y] != Tile::Box(id)),
+            );
+        }
+
+        for &id in &boxes {
+            let WarehouseBox { row, col, width } = self.boxes[id];
+            self.tiles[row][col..col + width].fill(Tile::Empty);
+        }
+        for &id in &boxes {
+            let b = &mut self.boxes[id];
+            (b.row, b.col) = shift((b.row, b.col));
+            self.tiles[b.row][b.col..b.col + b.width].fill(Tile::Box(id));
+        }
+        self.robot = shift(self.robot);
+
+        Some(boxes.len())
+    }
+
+    fn gps_sum(&self) -> usize {
+        self.boxes.iter().map(|b| b.row * 100 + b.col).sum()
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = match self {
+            Move::Left => '<',
+            Move::Up => '^',
+            Move::Right => '>',
+            Move::Down => 'v',
         };
+        write!(f, "{c}")
+    }
+}
+
+impl fmt::Display for Warehouse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, tiles) in self.tiles.iter().enumerate() {
+            for (col, tile) in tiles.iter().enumerate() {
+                let c = match *tile {
+                    _ if (row, col) == self.robot => '@',
+                    Tile::Wall => '#',
+                    Tile::Empty => '.',
+                    Tile::Box(id) => {
+                        let b = &self.boxes[id];
+                        match (b.width, col - b.col) {
+                            (1, _) => 'O',
+                            (_, 0) => '[',
+                            (width, offset) if offset == width - 1 => ']',
+                            _ => '=',
+                        }
+                    }
+                };
+                write!(f, "{c}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct MoveRecord {
+    step: usize,
+    robot_move: Move,
+    pushed: Option<usize>,
+}
 
-        let (x2, y2) = ((x as i32 + ox) as usize, (y as i32 + oy) as usize);
+impl fmt::Display for MoveRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pushed {
+            None => write!(f, "{:>5} {} blocked", self.step, self.robot_move),
+            Some(0) => write!(f, "{:>5} {}", self.step, self.robot_move),
+            Some(n) => write!(f, "{:>5} {} pushed {n}", self.step, self.robot_move),
+        }
+    }
+}
 
-        match field[x2][y2] {
-            Element::Robot => panic!("Multiple robots detected!"),
-            Element::Wall => {}
-            Element::Empty => {
-                (x, y) = move_robot(&mut field, x, y, x2, y2);
+struct Replay<'a> {
+    initial: Warehouse,
+    warehouse: Warehouse,
+    moves: &'a [Move],
+    next: usize,
+}
+
+impl<'a> Replay<'a> {
+    fn new(warehouse: Warehouse, moves: &'a [Move]) -> Self {
+        Replay {
+            initial: warehouse.clone(),
+            warehouse,
+            moves,
+            next: 0,
+        }
+    }
+
+    fn advance(&mut self) -> Option<MoveRecord> {
+        let &robot_move = self.moves.get(self.next)?;
+        self.next += 1;
+        Some(MoveRecord {
+            step: self.next,
+            robot_move,
+            pushed: self.warehouse.step(robot_move),
+        })
+    }
+
+    /// Jumping backwards replays all moves from the initial warehouse.
+    fn jump_to(&mut self, n: usize) -> Vec<MoveRecord> {
+        if n < self.next {
+            self.warehouse = self.initial.clone();
+            self.next = 0;
+        }
+        let mut log = Vec::new();
+        while self.next < n {
+            match self.advance() {
+                Some(record) => log.push(record),
+                None => break,
             }
-            Element::Box => {
-                let (mut x3, mut y3) = (x2, y2);
-                while field[x3][y3] == Element::Box {
-                    (x3, y3) = ((x3 as i32 + ox) as usize, (y3 as i32 + oy) as usize);
-                }
+        }
+        log
+    }
 
-                if field[x3][y3] == Element::Empty {
-                    move_box(&mut field, x2, y2, x3, y3);
-                    (x, y) = move_robot(&mut field, x, y, x2, y2);
+    fn warehouse(&self) -> &Warehouse {
+        &self.warehouse
+    }
+}
+
+impl Iterator for Replay<'_> {
+    type Item = (MoveRecord, Warehouse);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.advance()?;
+        Some((record, self.warehouse.clone()))
+    }
+}
+
+fn solve(field: &[Vec<Element>], moves: &[Move], scale: usize) -> usize {
+    let mut replay = Replay::new(Warehouse::new(field, scale), moves);
+    replay.jump_to(moves.len());
+    replay.warehouse().gps_sum()
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Goal {
+    GpsSum(usize),
+    Boxes(HashSet<(usize, usize)>),
+}
+
+impl Goal {
+    fn from_field(field: &[Vec<Element>], scale: usize) -> Self {
+        let mut targets = HashSet::new();
+        for (row, elements) in field.iter().enumerate() {
+            for (col, element) in elements.iter().enumerate() {
+                if matches!(element, Element::Box | Element::BoxLeft) {
+                    targets.insert((row, col * scale));
                 }
             }
-            Element::BoxLeft | Element::BoxRight => panic!("Unreachable"),
         }
+        Goal::Boxes(targets)
     }
 
-    field
-        .into_iter()
-        .enumerate()
-        .map(|(row_idx, row)| {
-            row.into_iter()
-                .enumerate()
-                .filter(|(_, ele)| *ele == Element::Box)
-                .map(|(col_idx, _)| row_idx * 100 + col_idx)
-                .sum::<usize>()
-        })
-        .sum::<usize>()
+    fn is_reached(&self, warehouse: &Warehouse) -> bool {
+        match self {
+            Goal::GpsSum(sum) => warehouse.gps_sum() == *sum,
+            Goal::Boxes(targets) => {
+                warehouse.boxes.len() == targets.len()
+                    && warehouse
+                        .boxes
+                        .iter()
+                        .all(|b| targets.contains(&(b.row, b.col)))
+            }
+        }
+    }
+
+    /// A box which can never be moved again because of the surrounding walls
+    /// has to be on a target already.
+    fn is_deadlocked(&self, warehouse: &Warehouse) -> bool {
+        match self {
+            // Frozen boxes only fix part of the sum, so nothing can be pruned.
+            Goal::GpsSum(_) => false,
+            Goal::Boxes(targets) => warehouse
+                .boxes
+                .iter()
+                .any(|b| !targets.contains(&(b.row, b.col)) && warehouse.is_frozen(b)),
+        }
+    }
 }
 
-fn solve2(init_field: &Vec<Vec<Element>>, moves: &Vec<Move>) -> usize {
-    let mut field = vec![vec![Element::Empty; init_field[0].len() * 2]; init_field.len()];
-    let mut robot_pos_opt = None;
+impl Warehouse {
+    fn is_frozen(&self, b: &WarehouseBox) -> bool {
+        let is_wall = |row: usize, col: usize| self.tiles[row][col] == Tile::Wall;
+        let cols = b.col..b.col + b.width;
 
-    for row in 0..init_field.len() {
-        for col in 0..init_field[row].len() {
-            if init_field[row][col] == Element::Robot {
-                robot_pos_opt = Some((row, 2 * col));
-                field[row][2 * col] = Element::Robot;
-            }
+        // Pushing up needs free tiles above and the robot below, pushing down
+        // vice versa.
+        let any_wall_above = cols.clone().any(|col| is_wall(b.row - 1, col));
+        let all_wall_above = cols.clone().all(|col| is_wall(b.row - 1, col));
+        let any_wall_below = cols.clone().any(|col| is_wall(b.row + 1, col));
+        let all_wall_below = cols.clone().all(|col| is_wall(b.row + 1, col));
+        let vertical = (!any_wall_above && !all_wall_below) || (!any_wall_below && !all_wall_above);
 
-            if init_field[row][col] == Element::Wall {
-                field[row][2 * col] = Element::Wall;
-                field[row][2 * col + 1] = Element::Wall;
+        let horizontal = !is_wall(b.row, b.col - 1) && !is_wall(b.row, b.col + b.width);
+
+        !vertical && !horizontal
+    }
+
+    fn state_key(&self) -> StateKey {
+        let mut boxes = self.boxes.clone();
+        boxes.sort_unstable();
+        (self.robot, boxes.into_boxed_slice())
+    }
+
+    fn load_state(&mut self, (robot, boxes): &StateKey) {
+        for b in &self.boxes {
+            self.tiles[b.row][b.col..b.col + b.width].fill(Tile::Empty);
+        }
+        self.boxes.clear();
+        self.boxes.extend_from_slice(boxes);
+        for (id, b) in self.boxes.iter().enumerate() {
+            self.tiles[b.row][b.col..b.col + b.width].fill(Tile::Box(id));
+        }
+        self.robot = *robot;
+    }
+}
+
+// Robot position and sorted boxes, identifies a state independent of box ids
+type StateKey = ((usize, usize), Box<[WarehouseBox]>);
+
+/// Finds a shortest move sequence which reaches `goal` using BFS over
+/// warehouse states, pruning states with boxes frozen off-target. Gives up
+/// after visiting `max_states` states.
+fn find_moves(warehouse: &Warehouse, goal: &Goal, max_states: usize) -> Option<Vec<Move>> {
+    // Only the compact states are kept (shared with `visited`), the tile grid
+    // is rebuilt in `current` for the state being expanded.
+    let root = Rc::new(warehouse.state_key());
+    // (state, parent index, move leading to this state)
+    let mut states: Vec<(Rc<StateKey>, usize, Option<Move>)> = vec![(root.clone(), 0, None)];
+    let mut visited = HashSet::from([root]);
+    let mut current = warehouse.clone();
+
+    let mut next_idx = 0;
+    while next_idx < states.len() && states.len() <= max_states {
+        let state = states[next_idx].0.clone();
+        current.load_state(&state);
+        if goal.is_reached(&current) {
+            let mut moves = Vec::new();
+            let mut idx = next_idx;
+            while let (_, parent, Some(robot_move)) = &states[idx] {
+                moves.push(*robot_move);
+                idx = *parent;
             }
+            moves.reverse();
+            return Some(moves);
+        }
 
-            if init_field[row][col] == Element::Box {
-                field[row][2 * col] = Element::BoxLeft;
-                field[row][2 * col + 1] = Element::BoxRight;
+        for robot_move in [Move::Left, Move::Up, Move::Right, Move::Down] {
+            current.load_state(&state);
+            if current.step(robot_move).is_none() || goal.is_deadlocked(&current) {
+                continue;
+            }
+            let next = Rc::new(current.state_key());
+            if visited.insert(next.clone()) {
+                states.push((next, next_idx, Some(robot_move)));
             }
         }
+
+        next_idx += 1;
     }
 
-    let (mut x, mut y) = robot_pos_opt.unwrap();
-
-    let move_robot = |field: &mut Vec<Vec<Element>>,
-                      old_x: usize,
-                      old_y: usize,
-                      new_x: usize,
-                      new_y: usize|
-     -> (usize, usize) {
-        field[old_x][old_y] = Element::Empty;
-        field[new_x][new_y] = Element::Robot;
-        (new_x, new_y)
-    };
-
-    for &robot_move in moves {
-        let (ox, oy) = match robot_move {
-            Move::Left => (0, -1),
-            Move::Right => (0, 1),
-            Move::Up => (-1, 0),
-            Move::Down => (1, 0),
-        };
+    None
+}
 
-        let (x2, y2) = ((x as i32 + ox) as usize, (y as i32 + oy) as usize);
+/// Interactive mode: renders the warehouse in the terminal and moves the
+/// robot with the arrow keys or `<^>v`. `u`/backspace undoes the last move,
+/// `q`/escape quits and prints the played moves.
+fn play(field: &[Vec<Element>], scale: usize) -> std::io::Result<()> {
+    terminal::enable_raw_mode()?;
+    execute!(stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
 
-        match field[x2][y2] {
-            Element::Robot => panic!("Multiple robots detected!"),
-            Element::Wall => {}
-            Element::Empty => {
-                (x, y) = move_robot(&mut field, x, y, x2, y2);
-            }
-            Element::Box => panic!("unreachable"),
-            Element::BoxLeft | Element::BoxRight => {
-                match robot_move {
-                    Move::Left | Move::Right => {
-                        // Can we push one to the left/right?
-                        let mut y3 = y2;
-                        while field[x][y3] == Element::BoxLeft || field[x][y3] == Element::BoxRight
-                        {
-                            y3 = (y3 as i32 + 2 * oy) as usize;
-                        }
+    let result = play_loop(Warehouse::new(field, scale));
 
-                        if field[x][y3] == Element::Empty {
-                            while y3 != y2 {
-                                let y4 = (y3 as i32 - oy) as usize;
-                                field[x][y3] = field[x][y4];
-                                y3 = y4;
-                            }
-                            (x, y) = move_robot(&mut field, x, y, x2, y2);
-                        }
-                    }
-                    Move::Down | Move::Up => {
-                        let mut is_movable = true;
-
-                        let mut boxes = Vec::new();
-                        let mut visited = HashSet::new();
-
-                        if field[x2][y2] == Element::BoxLeft {
-                            visited.insert((x2, y2, y2 + 1));
-                            boxes.push((x2, y2, y2 + 1));
-                        } else {
-                            visited.insert((x2, y2 - 1, y2));
-                            boxes.push((x2, y2 - 1, y2));
-                        }
+    execute!(stdout(), cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
 
-                        let mut next_idx = 0;
-                        while next_idx < boxes.len() {
-                            let (x_box, y_left, y_right) = boxes[next_idx];
-
-                            let x_box2 = (x_box as i32 + ox) as usize;
-
-                            if field[x_box2][y_left] == Element::Wall
-                                || field[x_box2][y_right] == Element::Wall
-                            {
-                                is_movable = false;
-                                break;
-                            }
-
-                            if field[x_box2][y_left] == Element::BoxLeft {
-                                let next = (x_box2, y_left, y_right);
-                                if !visited.contains(&next) {
-                                    visited.insert(next);
-                                    boxes.push(next);
-                                }
-                            }
-
-                            if field[x_box2][y_left] == Element::BoxRight {
-                                let next = (x_box2, y_left - 1, y_left);
-                                if !visited.contains(&next) {
-                                    visited.insert(next);
-                                    boxes.push(next);
-                                }
-                            }
-
-                            if field[x_box2][y_right] == Element::BoxLeft {
-                                let next = (x_box2, y_right, y_right + 1);
-                                if !visited.contains(&next) {
-                                    visited.insert(next);
-                                    boxes.push(next);
-                                }
-                            }
-
-                            next_idx += 1;
-                        }
+    let played = result?;
+    println!("{}", played.iter().map(Move::to_string).collect::<String>());
+    Ok(())
+}
 
-                        if is_movable {
-                            for (source_x, y_left, y_right) in boxes.into_iter().rev() {
-                                let target_x = (source_x as i32 + ox) as usize;
-                                field[target_x][y_left] = Element::BoxLeft;
-                                field[source_x][y_left] = Element::Empty;
-                                field[target_x][y_right] = Element::BoxRight;
-                                field[source_x][y_right] = Element::Empty;
-                            }
-                            (x, y) = move_robot(&mut field, x, y, x2, y2);
-                        }
+fn play_loop(mut warehouse: Warehouse) -> std::io::Result<Vec<Move>> {
+    let mut history: Vec<(Move, Warehouse)> = Vec::new();
+    let mut status = String::new();
+
+    loop {
+        let mut out = stdout();
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        // Raw mode does not translate `\n` into a carriage return.
+        for line in warehouse.to_string().lines() {
+            write!(out, "{line}\r\n")?;
+        }
+        write!(
+            out,
+            "\r\nMoves: {}  GPS sum: {}  {status}\r\n",
+            history.len(),
+            warehouse.gps_sum()
+        )?;
+        write!(out, "arrows/<^>v: move  u: undo  q: quit\r\n")?;
+        out.flush()?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let robot_move = match key.code {
+            KeyCode::Left | KeyCode::Char('<') => Move::Left,
+            KeyCode::Up | KeyCode::Char('^') => Move::Up,
+            KeyCode::Right | KeyCode::Char('>') => Move::Right,
+            KeyCode::Down | KeyCode::Char('v') => Move::Down,
+            KeyCode::Char('u') | KeyCode::Backspace => {
+                status = match history.pop() {
+                    Some((robot_move, previous)) => {
+                        warehouse = previous;
+                        format!("undid {robot_move}")
                     }
-                }
+                    None => "nothing to undo".to_string(),
+                };
+                continue;
             }
-        }
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            _ => continue,
+        };
 
-        // println!("Move: {:?}", robot_move);
-        // for row in 0..field.len() {
-        //     for col in 0..field[row].len() {
-        //         match field[row][col] {
-        //             Element::Box => {},
-        //             Element::BoxLeft => print!("["),
-        //             Element::BoxRight => print!("]"),
-        //             Element::Robot => print!("@"),
-        //             Element::Empty => print!("."),
-        //             Element::Wall => print!("#"),
-        //         }
-        //     }
-        //     println!("");
-        // }
+        let previous = warehouse.clone();
+        status = match warehouse.step(robot_move) {
+            None => "blocked".to_string(),
+            Some(0) => String::new(),
+            Some(n) => format!("pushed {n}"),
+        };
+        history.push((robot_move, previous));
     }
 
-    field
+    Ok(history
         .into_iter()
-        .enumerate()
-        .map(|(row_idx, row)| {
-            row.into_iter()
-                .enumerate()
-                .filter(|(_, ele)| *ele == Element::BoxLeft)
-                .map(|(col_idx, _)| row_idx * 100 + col_idx)
-                .sum::<usize>()
-        })
-        .sum::<usize>()
+        .map(|(robot_move, _)| robot_move)
+        .collect())
 }
 
+// Every state keeps its sorted boxes, about 15 KB for the 600 boxes of the
+// input, so this bounds the search to roughly 150 MB.
+const MAX_SEARCH_STATES: usize = 10_000;
+
 fn main() -> std::io::Result<()> {
     // let (field, moves) = read_data("example2.txt")?;
     // let (field, moves) = read_data("example.txt")?;
@@ -312,14 +553,52 @@ fn main() -> std::io::Result<()> {
     let (field, moves) = read_data("input.txt")?;
 
     let start = std::time::Instant::now();
-    let task1 = solve(field.clone(), &moves);
+    let task1 = solve(&field, &moves, 1);
     let duration = start.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start = std::time::Instant::now();
-    let task2 = solve2(&field, &moves);
+    let task2 = solve(&field, &moves, 2);
     let duration = start.elapsed();
     println!("Task 2: {task2} (took {:?})", duration);
 
+    // Debugging aids:
+    // - `cargo run -- <scale> <n>` prints the log and the warehouse after move n
+    // - `cargo run -- play <scale>` drives the robot manually
+    // - `cargo run -- solve <scale> <gps sum | target map>` searches moves
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        [] => {}
+        ["play", scale] => play(&field, scale.parse().expect("Invalid scale"))?,
+        ["solve", scale, target] => {
+            let scale = scale.parse().expect("Invalid scale");
+            let goal = match target.parse() {
+                Ok(sum) => Goal::GpsSum(sum),
+                Err(_) => {
+                    let (target_field, _) = read_data(target)?;
+                    Goal::from_field(&target_field, scale)
+                }
+            };
+
+            match find_moves(&Warehouse::new(&field, scale), &goal, MAX_SEARCH_STATES) {
+                Some(moves) => {
+                    println!("{}", moves.iter().map(Move::to_string).collect::<String>())
+                }
+                None => println!("No move sequence found"),
+            }
+        }
+        [scale, n] => {
+            let scale = scale.parse().expect("Invalid scale");
+            let n = n.parse().expect("Invalid move index");
+            let mut replay = Replay::new(Warehouse::new(&field, scale), &moves);
+            for record in replay.jump_to(n) {
+                println!("{record}");
+            }
+            print!("{}", replay.warehouse());
+        }
+        _ => panic!("Expected `<scale> <n>`, `play <scale>` or `solve <scale> <target>`"),
+    }
+
     Ok(())
 }
@@ -1,17 +1,17 @@
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs::read_to_string;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-#[repr(u8)]
 enum Element {
     Wall,
     Empty,
     Start,
     Target,
+    Terrain(char),
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[repr(u8)]
 enum Direction {
     Up,
@@ -20,6 +20,36 @@ enum Direction {
     Left,
 }
 
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+impl Direction {
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Right => (0, 1),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+        }
+    }
+
+    fn clockwise_turns(self, next: Direction) -> u8 {
+        (next as u8 + 4 - self as u8) % 4
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Action {
+    Forward,
+    TurnLeft,
+    TurnRight,
+    TurnAround,
+}
+
 fn read_data(path: &str) -> std::io::Result<Vec<Vec<Element>>> {
     Ok(read_to_string(path)?
         .split("\n")
@@ -30,7 +60,7 @@ fn read_data(path: &str) -> std::io::Result<Vec<Vec<Element>>> {
                     '#' => Element::Wall,
                     'S' => Element::Start,
                     'E' => Element::Target,
-                    _ => panic!("invalid char"),
+                    c => Element::Terrain(c),
                 })
                 .collect()
         })
@@ -39,124 +69,398 @@ fn read_data(path: &str) -> std::io::Result<Vec<Vec<Element>>> {
 
 const TURN_COST: i64 = 1000;
 
-fn solve(field: &Vec<Vec<Element>>, is_part_2: bool) -> i64 {
-    let start = (field.len() - 2, 1);
-    let target = (1, field[0].len() - 2);
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CostModel {
+    step: i64,
+    turn_left: i64,
+    turn_right: i64,
+    turn_around: i64,
+    terrain: HashMap<char, i64>,
+}
+
+impl Default for CostModel {
+    /// The puzzle rules: a 180 degree turn is two quarter turns.
+    fn default() -> Self {
+        CostModel {
+            step: 1,
+            turn_left: TURN_COST,
+            turn_right: TURN_COST,
+            turn_around: 2 * TURN_COST,
+            terrain: HashMap::new(),
+        }
+    }
+}
+
+const TURN_SEQUENCES: [&[&[Action]]; 3] = [
+    &[
+        &[Action::TurnRight],
+        &[Action::TurnLeft, Action::TurnAround],
+        &[Action::TurnLeft, Action::TurnLeft, Action::TurnLeft],
+    ],
+    &[
+        &[Action::TurnAround],
+        &[Action::TurnRight, Action::TurnRight],
+        &[Action::TurnLeft, Action::TurnLeft],
+    ],
+    &[
+        &[Action::TurnLeft],
+        &[Action::TurnRight, Action::TurnAround],
+        &[Action::TurnRight, Action::TurnRight, Action::TurnRight],
+    ],
+];
+
+impl CostModel {
+    fn action_cost(&self, action: Action) -> i64 {
+        match action {
+            Action::Forward => self.step,
+            Action::TurnLeft => self.turn_left,
+            Action::TurnRight => self.turn_right,
+            Action::TurnAround => self.turn_around,
+        }
+    }
+
+    fn turn(&self, from: Direction, to: Direction) -> (i64, &'static [Action]) {
+        match from.clockwise_turns(to) {
+            0 => (0, &[]),
+            turns => TURN_SEQUENCES[turns as usize - 1]
+                .iter()
+                .map(|&actions| {
+                    let cost = actions.iter().map(|&a| self.action_cost(a)).sum();
+                    (cost, actions)
+                })
+                .min_by_key(|&(cost, _)| cost)
+                .unwrap(),
+        }
+    }
+
+    fn enter(&self, element: Element) -> Option<i64> {
+        match element {
+            Element::Wall => None,
+            Element::Empty | Element::Start | Element::Target => Some(self.step),
+            Element::Terrain(glyph) => Some(
+                *self
+                    .terrain
+                    .get(&glyph)
+                    .unwrap_or_else(|| panic!("No cost for terrain '{glyph}'")),
+            ),
+        }
+    }
+
+    /// Every edge contains a step, so positive step costs make sure that
+    /// predecessors are always finalized before their successors.
+    fn validate(&self) {
+        assert!(
+            [self.turn_left, self.turn_right, self.turn_around]
+                .into_iter()
+                .all(|cost| cost >= 0),
+            "Turn costs must not be negative"
+        );
+        assert!(
+            std::iter::once(self.step)
+                .chain(self.terrain.values().copied())
+                .all(|cost| cost > 0),
+            "Step costs must be positive"
+        );
+    }
+}
+
+const START_DIRECTION: Direction = Direction::Right;
+
+fn find_tiles(field: &[Vec<Element>], element: Element) -> Vec<(usize, usize)> {
+    field
+        .iter()
+        .enumerate()
+        .flat_map(|(x, row)| {
+            row.iter()
+                .enumerate()
+                .filter(move |&(_, &ele)| ele == element)
+                .map(move |(y, _)| (x, y))
+        })
+        .collect()
+}
+
+/// Tile and the direction in which the reindeer entered it.
+type State = (usize, usize, Direction);
+
+#[derive(Debug, Clone)]
+struct BestPaths {
+    score: i64,
+    tiles: HashSet<(usize, usize)>,
+    /// Number of distinct best paths, i.e. distinct tile sequences.
+    path_count: u128,
+    start: State,
+    ends: Vec<State>,
+    /// Headings of the optimal predecessors of each state. The predecessor
+    /// tile follows from the heading of the state itself.
+    predecessors: Vec<Vec<[Vec<Direction>; 4]>>,
+    model: CostModel,
+}
+
+/// Dijkstra over `(tile, heading)` states. Every edge is the cheapest turn
+/// sequence (if any) followed by one step forward, so each best path through
+/// the state graph corresponds to exactly one tile sequence.
+fn best_paths(
+    field: &[Vec<Element>],
+    start_dir: Direction,
+    model: &CostModel,
+) -> Option<BestPaths> {
+    model.validate();
+
+    let start = match find_tiles(field, Element::Start)[..] {
+        [start] => start,
+        _ => panic!("Expected exactly one start tile"),
+    };
+    let targets = find_tiles(field, Element::Target);
+    assert!(!targets.is_empty(), "No target tile found");
+
+    let start_state = (start.0, start.1, start_dir);
 
-    let mut visited: Vec<Vec<[(i64, Vec<(usize, usize, Direction)>); 4]>> =
-        vec![vec![std::array::from_fn(|_| (i64::MAX, Vec::new())); field[0].len()]; field.len()];
+    let mut costs = vec![vec![[i64::MAX; 4]; field[0].len()]; field.len()];
+    let mut predecessors: Vec<Vec<[Vec<Direction>; 4]>> =
+        vec![vec![std::array::from_fn(|_| Vec::new()); field[0].len()]; field.len()];
+    // States in the order in which their cost became final.
+    let mut order = Vec::new();
+    let mut best_score = None;
 
     let mut heap = BinaryHeap::new();
-    heap.push((
-        Reverse(0),
-        start.0,
-        start.1,
-        Direction::Right,
-        (start.0, 0, Direction::Right),
-    ));
-
-    while let Some((Reverse(cost), x, y, dir, src)) = heap.pop() {
-        if (x, y) == target {
-            if !is_part_2 {
-                return cost;
-            }
+    costs[start.0][start.1][start_dir as usize] = 0;
+    heap.push((Reverse(0), start_state));
 
-            // Collect all the possible solution
-            visited[x][y][dir as usize].1.push(src);
-            while let Some((Reverse(cost2), x2, y2, dir, src)) = heap.pop() {
-                if cost2 > cost || (x2, y2) != target {
-                    break;
-                }
-                visited[x][y][dir as usize].1.push(src);
-            }
+    while let Some((Reverse(cost), (x, y, dir))) = heap.pop() {
+        if cost > costs[x][y][dir as usize] {
+            continue;
+        }
+        if best_score.is_some_and(|best| cost > best) {
+            break;
+        }
+        order.push((x, y, dir));
 
-            // Collect all paths
-            let mut queue = VecDeque::new();
-            let mut visited_tiles = HashSet::new();
-            visited_tiles.insert(target);
+        if targets.contains(&(x, y)) {
+            best_score = Some(cost);
+            continue;
+        }
 
-            for dir in 0..4 {
-                for &(x, y, dir) in &visited[target.0][target.1][dir].1 {
-                    queue.push_back((x, y, dir));
-                    visited_tiles.insert((x, y));
-                }
-            }
+        for next_dir in DIRECTIONS {
+            let (ox, oy) = next_dir.offset();
+            let (Some(x2), Some(y2)) = (x.checked_add_signed(ox), y.checked_add_signed(oy)) else {
+                continue;
+            };
+            let Some(enter_cost) = field
+                .get(x2)
+                .and_then(|row| row.get(y2))
+                .and_then(|&ele| model.enter(ele))
+            else {
+                continue;
+            };
 
-            while let Some((x, y, dir)) = queue.pop_front() {
-                visited_tiles.insert((x, y));
-                if (x, y) == start {
-                    break;
-                }
-                queue.extend(visited[x][y][dir as usize].1.iter().map(|&src| src));
+            let total_cost = cost + model.turn(dir, next_dir).0 + enter_cost;
+            let best = &mut costs[x2][y2][next_dir as usize];
+            if total_cost < *best {
+                *best = total_cost;
+                predecessors[x2][y2][next_dir as usize] = vec![dir];
+                heap.push((Reverse(total_cost), (x2, y2, next_dir)));
+            } else if total_cost == *best {
+                predecessors[x2][y2][next_dir as usize].push(dir);
             }
+        }
+    }
 
-            return visited_tiles.len() as i64;
+    let score = best_score?;
+    let ends: Vec<State> = order
+        .iter()
+        .copied()
+        .filter(|&(x, y, dir)| targets.contains(&(x, y)) && costs[x][y][dir as usize] == score)
+        .collect();
+
+    // Predecessors are always finalized earlier, so the path counts can be
+    // accumulated in the order of the search.
+    let mut counts = vec![vec![[0u128; 4]; field[0].len()]; field.len()];
+    counts[start.0][start.1][start_dir as usize] = 1;
+    for &(x, y, dir) in &order {
+        let (px, py) = previous_tile((x, y, dir));
+        for &prev_dir in &predecessors[x][y][dir as usize] {
+            counts[x][y][dir as usize] += counts[px][py][prev_dir as usize];
         }
+    }
+    let path_count = ends
+        .iter()
+        .map(|&(x, y, dir)| counts[x][y][dir as usize])
+        .sum();
 
-        if cost > visited[x][y][dir as usize].0 {
-            continue;
+    // A state lies on a best path iff the best cost to reach it plus the best
+    // cost from it to any best end state equals the score.
+    let remaining = reverse_costs(field, model, &ends, score);
+    let mut tiles = HashSet::new();
+    for (x, row) in costs.iter().enumerate() {
+        for (y, dirs) in row.iter().enumerate() {
+            let on_best_path = dirs
+                .iter()
+                .zip(&remaining[x][y])
+                .any(|(&to, &from)| to.checked_add(from).is_some_and(|total| total == score));
+            if on_best_path {
+                tiles.insert((x, y));
+            }
         }
-        if cost == visited[x][y][dir as usize].0 {
-            visited[x][y][dir as usize].1.push(src);
+    }
+
+    Some(BestPaths {
+        score,
+        tiles,
+        path_count,
+        start: start_state,
+        ends,
+        predecessors,
+        model: model.clone(),
+    })
+}
+
+/// Dijkstra on the reversed state graph from all `ends`, exploring states up
+/// to cost `limit`.
+fn reverse_costs(
+    field: &[Vec<Element>],
+    model: &CostModel,
+    ends: &[State],
+    limit: i64,
+) -> Vec<Vec<[i64; 4]>> {
+    let mut costs = vec![vec![[i64::MAX; 4]; field[0].len()]; field.len()];
+
+    let mut heap = BinaryHeap::new();
+    for &(x, y, dir) in ends {
+        costs[x][y][dir as usize] = 0;
+        heap.push((Reverse(0), (x, y, dir)));
+    }
+
+    while let Some((Reverse(cost), state)) = heap.pop() {
+        let (x, y, dir) = state;
+        if cost > costs[x][y][dir as usize] || cost > limit {
             continue;
         }
-        visited[x][y][dir as usize] = (cost, vec![src]);
-
-        let (x2, y2, next_dirs) = match dir {
-            Direction::Down => (
-                x + 1,
-                y,
-                [
-                    (Direction::Left, 1),
-                    (Direction::Right, 1),
-                    (Direction::Up, 2),
-                ],
-            ),
-            Direction::Left => (
-                x,
-                y - 1,
-                [
-                    (Direction::Up, 1),
-                    (Direction::Down, 1),
-                    (Direction::Right, 2),
-                ],
-            ),
-            Direction::Right => (
-                x,
-                y + 1,
-                [
-                    (Direction::Up, 1),
-                    (Direction::Down, 1),
-                    (Direction::Left, 2),
-                ],
-            ),
-            Direction::Up => (
-                x - 1,
-                y,
-                [
-                    (Direction::Left, 1),
-                    (Direction::Right, 1),
-                    (Direction::Down, 2),
-                ],
-            ),
+
+        // The state was entered from the previous tile facing any direction.
+        let (px, py) = previous_tile(state);
+        let Some(enter_cost) = model.enter(field[x][y]) else {
+            continue;
         };
+        if field
+            .get(px)
+            .and_then(|row| row.get(py))
+            .is_none_or(|&ele| ele == Element::Wall)
+        {
+            continue;
+        }
+
+        for prev_dir in DIRECTIONS {
+            let total_cost = cost + model.turn(prev_dir, dir).0 + enter_cost;
+            if total_cost < costs[px][py][prev_dir as usize] {
+                costs[px][py][prev_dir as usize] = total_cost;
+                heap.push((Reverse(total_cost), (px, py, prev_dir)));
+            }
+        }
+    }
+
+    costs
+}
+
+fn previous_tile((x, y, dir): State) -> (usize, usize) {
+    let (ox, oy) = dir.offset();
+    (x.wrapping_add_signed(-ox), y.wrapping_add_signed(-oy))
+}
+
+impl BestPaths {
+    fn predecessors_of(&self, state: State) -> impl Iterator<Item = State> + '_ {
+        let (x, y, dir) = state;
+        let (px, py) = previous_tile(state);
+        self.predecessors[x][y][dir as usize]
+            .iter()
+            .map(move |&prev_dir| (px, py, prev_dir))
+    }
 
-        if field[x2][y2] != Element::Wall {
-            let total_cost = cost + 1;
-            if total_cost <= visited[x2][y2][dir as usize].0 {
-                heap.push((Reverse(total_cost), x2, y2, dir, (x, y, dir)));
+    fn actions(&self, states: &[State]) -> Vec<Action> {
+        states
+            .windows(2)
+            .flat_map(|pair| {
+                self.model
+                    .turn(pair[0].2, pair[1].2)
+                    .1
+                    .iter()
+                    .copied()
+                    .chain([Action::Forward])
+            })
+            .collect()
+    }
+
+    fn route(&self) -> Vec<Action> {
+        let mut states = vec![self.ends[0]];
+        while let Some(prev) = self.predecessors_of(*states.last().unwrap()).next() {
+            states.push(prev);
+        }
+        states.reverse();
+        self.actions(&states)
+    }
+
+    fn routes(&self, limit: usize) -> Vec<Vec<Action>> {
+        let mut routes = Vec::new();
+        // Partial paths from an end state backwards
+        let mut stack: Vec<Vec<State>> = self.ends.iter().map(|&end| vec![end]).collect();
+        while let Some(states) = stack.pop() {
+            if routes.len() >= limit {
+                break;
+            }
+
+            let last = *states.last().unwrap();
+            if last == self.start {
+                let mut states = states;
+                states.reverse();
+                routes.push(self.actions(&states));
+                continue;
+            }
+
+            for prev in self.predecessors_of(last) {
+                let mut next = states.clone();
+                next.push(prev);
+                stack.push(next);
             }
         }
+        routes
+    }
 
-        for (next_dir, turns) in next_dirs {
-            let total_cost = cost + turns * TURN_COST;
-            if total_cost <= visited[x][y][next_dir as usize].0 {
-                heap.push((Reverse(total_cost), x, y, next_dir, (x, y, dir)));
+    fn render(&self, field: &[Vec<Element>]) -> String {
+        let mut out = String::new();
+        for (x, row) in field.iter().enumerate() {
+            for (y, ele) in row.iter().enumerate() {
+                out.push(match ele {
+                    _ if self.tiles.contains(&(x, y)) => 'O',
+                    Element::Wall => '#',
+                    Element::Empty => '.',
+                    Element::Start => 'S',
+                    Element::Target => 'E',
+                    Element::Terrain(glyph) => *glyph,
+                });
             }
+            out.push('\n');
         }
+        out
     }
+}
 
-    -1
+fn format_route(route: &[Action]) -> String {
+    route
+        .iter()
+        .map(|action| match action {
+            Action::Forward => 'F',
+            Action::TurnLeft => 'L',
+            Action::TurnRight => 'R',
+            Action::TurnAround => 'U',
+        })
+        .collect()
+}
+
+fn solve(field: &[Vec<Element>], start_dir: Direction, model: &CostModel, is_part_2: bool) -> i64 {
+    match best_paths(field, start_dir, model) {
+        Some(best) if is_part_2 => best.tiles.len() as i64,
+        Some(best) => best.score,
+        None => -1,
+    }
 }
 
 const PART_1: bool = false;
@@ -166,16 +470,52 @@ fn main() -> std::io::Result<()> {
     // let field = read_data("example.txt")?;
     // let field = read_data("example2.txt")?;
     let field = read_data("input.txt")?;
+    let model = CostModel::default();
 
     let start = std::time::Instant::now();
-    let task1 = solve(&field, PART_1);
+    let task1 = solve(&field, START_DIRECTION, &model, PART_1);
     let duration = start.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start = std::time::Instant::now();
-    let task2 = solve(&field, PART_2);
+    let task2 = solve(&field, START_DIRECTION, &model, PART_2);
     let duration = start.elapsed();
     println!("Task 2: {task2} (took {:?})", duration);
 
+    // Show the best paths with `cargo run -- routes`
+    if std::env::args().nth(1).as_deref() == Some("routes") {
+        if let Some(best) = best_paths(&field, START_DIRECTION, &model) {
+            print!("{}", best.render(&field));
+            println!("Best paths: {}", best.path_count);
+            println!("Route: {}", format_route(&best.route()));
+            for route in best.routes(10) {
+                println!("  {}", format_route(&route));
+            }
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(path: &str, score: i64, tiles: usize, path_count: u128) {
+        let field = read_data(path).unwrap();
+        let best = best_paths(&field, START_DIRECTION, &CostModel::default()).unwrap();
+        assert_eq!(best.score, score);
+        assert_eq!(best.tiles.len(), tiles);
+        assert_eq!(best.path_count, path_count);
+    }
+
+    #[test]
+    fn example() {
+        check("example.txt", 7036, 45, 3);
+    }
+
+    #[test]
+    fn example2() {
+        check("example2.txt", 11048, 64, 2);
+    }
+}
@@ -0,0 +1,5 @@
+; Quine from the part 2 example (Program: 0,3,5,4,3,0)
+loop:
+    adv 3       ; A := A / 8
+    out A       ; print(A % 8)
+    jnz loop    ; if A != 0 goto loop
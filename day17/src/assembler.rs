@@ -0,0 +1,232 @@
+use crate::machine::Opcode;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssembleError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    MissingOperand {
+        line: usize,
+    },
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    OperandOutOfRange {
+        line: usize,
+        value: u64,
+    },
+    ReservedComboOperand {
+        line: usize,
+    },
+    UnexpectedToken {
+        line: usize,
+        token: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+    UnknownLabel {
+        line: usize,
+        label: String,
+    },
+    /// Jump targets are literal operands, so labels have to be within the
+    /// first 8 program positions.
+    LabelOutOfRange {
+        line: usize,
+        label: String,
+        address: usize,
+    },
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssembleError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {line}: unknown mnemonic `{mnemonic}`")
+            }
+            AssembleError::MissingOperand { line } => write!(f, "line {line}: missing operand"),
+            AssembleError::InvalidOperand { line, operand } => {
+                write!(f, "line {line}: invalid operand `{operand}`")
+            }
+            AssembleError::OperandOutOfRange { line, value } => {
+                write!(f, "line {line}: operand {value} does not fit into 3 bits")
+            }
+            AssembleError::ReservedComboOperand { line } => {
+                write!(f, "line {line}: combo operand 7 is reserved")
+            }
+            AssembleError::UnexpectedToken { line, token } => {
+                write!(f, "line {line}: unexpected `{token}`")
+            }
+            AssembleError::DuplicateLabel { line, label } => {
+                write!(f, "line {line}: label `{label}` is already defined")
+            }
+            AssembleError::UnknownLabel { line, label } => {
+                write!(f, "line {line}: unknown label `{label}`")
+            }
+            AssembleError::LabelOutOfRange {
+                line,
+                label,
+                address,
+            } => write!(
+                f,
+                "line {line}: label `{label}` at {address} is out of jump range"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+impl From<AssembleError> for std::io::Error {
+    fn from(err: AssembleError) -> Self {
+        std::io::Error::other(err)
+    }
+}
+
+fn parse_mnemonic(mnemonic: &str) -> Option<Opcode> {
+    (0..8)
+        .filter_map(Opcode::from_u8)
+        .find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+}
+
+fn is_label(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+enum Operand {
+    Value(u8),
+    Label(String),
+}
+
+fn parse_literal(line: usize, token: &str) -> Result<u8, AssembleError> {
+    let value = token
+        .parse::<u64>()
+        .map_err(|_| AssembleError::InvalidOperand {
+            line,
+            operand: token.to_string(),
+        })?;
+    if value > 7 {
+        return Err(AssembleError::OperandOutOfRange { line, value });
+    }
+    Ok(value as u8)
+}
+
+fn parse_combo(line: usize, token: &str) -> Result<u8, AssembleError> {
+    match token.to_ascii_uppercase().as_str() {
+        "A" => Ok(4),
+        "B" => Ok(5),
+        "C" => Ok(6),
+        _ => match parse_literal(line, token)? {
+            7 => Err(AssembleError::ReservedComboOperand { line }),
+            value => Ok(value),
+        },
+    }
+}
+
+/// Assembles mnemonic source into the program's opcodes.
+///
+/// Every line holds at most one instruction (`bst A`, `bxl 5`, `jnz loop`),
+/// optionally preceded by labels (`loop:`). Combo operands are written as
+/// `0`-`3` or `A`/`B`/`C`, `jnz` takes a number or a label and the operand of
+/// `bxc` may be omitted. Comments start with `;`. The output of the
+/// disassembler (`  4: cdv B`, `bxc (2)`) is accepted as well.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut instructions = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let line_number = idx + 1;
+        let code = line.split(';').next().unwrap();
+        let mut tokens = code.split_whitespace().peekable();
+
+        // Labels and disassembler addresses
+        while let Some(token) = tokens.peek() {
+            let Some(name) = token.strip_suffix(':') else {
+                break;
+            };
+            if is_label(name) {
+                let address = instructions.len() * 2;
+                if labels.insert(name.to_string(), address).is_some() {
+                    return Err(AssembleError::DuplicateLabel {
+                        line: line_number,
+                        label: name.to_string(),
+                    });
+                }
+            } else if name.parse::<usize>().is_err() {
+                return Err(AssembleError::UnexpectedToken {
+                    line: line_number,
+                    token: token.to_string(),
+                });
+            }
+            tokens.next();
+        }
+
+        let Some(mnemonic) = tokens.next() else {
+            continue;
+        };
+        let opcode = parse_mnemonic(mnemonic).ok_or(AssembleError::UnknownMnemonic {
+            line: line_number,
+            mnemonic: mnemonic.to_string(),
+        })?;
+
+        let operand = tokens.next();
+        if let Some(token) = tokens.next() {
+            return Err(AssembleError::UnexpectedToken {
+                line: line_number,
+                token: token.to_string(),
+            });
+        }
+
+        let operand = match (opcode, operand) {
+            (Opcode::Bxc, None) => Operand::Value(0),
+            (Opcode::Bxc, Some(token)) => {
+                let token = token.trim_start_matches('(').trim_end_matches(')');
+                Operand::Value(parse_literal(line_number, token)?)
+            }
+            (_, None) => return Err(AssembleError::MissingOperand { line: line_number }),
+            (Opcode::Jnz, Some(token)) if is_label(token) => Operand::Label(token.to_string()),
+            (opcode, Some(token)) if opcode.takes_combo() => {
+                Operand::Value(parse_combo(line_number, token)?)
+            }
+            (_, Some(token)) => Operand::Value(parse_literal(line_number, token)?),
+        };
+
+        instructions.push((line_number, opcode, operand));
+    }
+
+    let mut program = Vec::with_capacity(instructions.len() * 2);
+    for (line, opcode, operand) in instructions {
+        let operand = match operand {
+            Operand::Value(value) => value,
+            Operand::Label(label) => match labels.get(&label) {
+                Some(&address) if address <= 7 => address as u8,
+                Some(&address) => {
+                    return Err(AssembleError::LabelOutOfRange {
+                        line,
+                        label,
+                        address,
+                    })
+                }
+                None => return Err(AssembleError::UnknownLabel { line, label }),
+            },
+        };
+        program.push(opcode as u8);
+        program.push(operand);
+    }
+
+    Ok(program)
+}
+
+pub fn program_line(program: &[u8]) -> String {
+    let opcodes: Vec<String> = program.iter().map(|num| num.to_string()).collect();
+    format!("Program: {}", opcodes.join(","))
+}
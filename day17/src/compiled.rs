@@ -0,0 +1,157 @@
+use crate::machine::{Machine, MachineError, Opcode};
+use rayon::prelude::*;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Src {
+    Literal(u64),
+    A,
+    B,
+    C,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Adv(Src),
+    Bxl(u64),
+    Bst(Src),
+    Jnz(usize),
+    Bxc,
+    Out(Src),
+    Bdv(Src),
+    Cdv(Src),
+    /// Only fails once it is executed, like in the interpreter.
+    Invalid(MachineError),
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    /// One op per instruction pointer, since jumps may target odd positions.
+    ops: Vec<Op>,
+    b: u64,
+    c: u64,
+}
+
+impl CompiledProgram {
+    pub fn compile(machine: &Machine) -> Self {
+        let ops = (0..machine.program.len())
+            .map(|ip| match machine.decode(ip) {
+                Ok(Some(instruction)) => {
+                    let operand = instruction.operand;
+                    let src = match operand {
+                        0..=3 => Src::Literal(operand as u64),
+                        4 => Src::A,
+                        5 => Src::B,
+                        6 => Src::C,
+                        _ if instruction.opcode.takes_combo() => {
+                            return Op::Invalid(MachineError::InvalidComboOperand { ip, operand })
+                        }
+                        // Not used as combo operand
+                        _ => Src::Literal(0),
+                    };
+                    match instruction.opcode {
+                        Opcode::Adv => Op::Adv(src),
+                        Opcode::Bxl => Op::Bxl(operand as u64),
+                        Opcode::Bst => Op::Bst(src),
+                        Opcode::Jnz => Op::Jnz(operand as usize),
+                        Opcode::Bxc => Op::Bxc,
+                        Opcode::Out => Op::Out(src),
+                        Opcode::Bdv => Op::Bdv(src),
+                        Opcode::Cdv => Op::Cdv(src),
+                    }
+                }
+                Ok(None) => unreachable!(),
+                Err(err) => Op::Invalid(err),
+            })
+            .collect();
+
+        CompiledProgram {
+            ops,
+            b: machine.registers.b,
+            c: machine.registers.c,
+        }
+    }
+
+    /// Runs the program with register A set to `a` and passes every output to
+    /// `emit`. Stops early once `emit` returns `false`.
+    #[inline]
+    fn execute(
+        &self,
+        a: u64,
+        max_steps: usize,
+        mut emit: impl FnMut(u8) -> bool,
+    ) -> Result<(), MachineError> {
+        let (mut a, mut b, mut c) = (a, self.b, self.c);
+        let mut ip = 0;
+
+        for _ in 0..max_steps {
+            let Some(op) = self.ops.get(ip) else {
+                return Ok(());
+            };
+
+            let value = |src: Src, a: u64, b: u64, c: u64| match src {
+                Src::Literal(value) => value,
+                Src::A => a,
+                Src::B => b,
+                Src::C => c,
+            };
+            let divide = |a: u64, shift: u64| if shift >= 64 { 0 } else { a >> shift };
+
+            ip += 2;
+            match *op {
+                Op::Adv(src) => a = divide(a, value(src, a, b, c)),
+                Op::Bxl(literal) => b ^= literal,
+                Op::Bst(src) => b = value(src, a, b, c) % 8,
+                Op::Jnz(target) => {
+                    if a != 0 {
+                        ip = target;
+                    }
+                }
+                Op::Bxc => b ^= c,
+                Op::Out(src) => {
+                    if !emit((value(src, a, b, c) % 8) as u8) {
+                        return Ok(());
+                    }
+                }
+                Op::Bdv(src) => b = divide(a, value(src, a, b, c)),
+                Op::Cdv(src) => c = divide(a, value(src, a, b, c)),
+                Op::Invalid(ref err) => return Err(err.clone()),
+            }
+        }
+
+        Err(MachineError::StepLimitExceeded { limit: max_steps })
+    }
+
+    pub fn run(&self, a: u64, max_steps: usize) -> Result<Vec<u64>, MachineError> {
+        let mut out = Vec::new();
+        self.execute(a, max_steps, |value| {
+            out.push(value as u64);
+            true
+        })?;
+        Ok(out)
+    }
+
+    /// Whether the output starts with `prefix`. Stops as soon as an output
+    /// differs or the whole prefix matched, so only errors before that point
+    /// make a run fail to match.
+    pub fn output_starts_with(&self, a: u64, prefix: &[u8], max_steps: usize) -> bool {
+        if prefix.is_empty() {
+            return true;
+        }
+        let mut matched = 0;
+        let mut mismatch = false;
+        let _ = self.execute(a, max_steps, |value| {
+            mismatch = prefix[matched] != value;
+            matched += 1;
+            !mismatch && matched < prefix.len()
+        });
+        !mismatch && matched == prefix.len()
+    }
+
+    pub fn search(&self, range: Range<u64>, prefix: &[u8], max_steps: usize) -> Vec<u64> {
+        range
+            .into_par_iter()
+            .filter(|&a| self.output_starts_with(a, prefix, max_steps))
+            .collect()
+    }
+}
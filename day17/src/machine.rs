@@ -0,0 +1,299 @@
+use std::fmt;
+
+// Catches programs that never halt
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u64,
+    pub b: u64,
+    pub c: u64,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "A={} B={} C={}", self.a, self.b, self.c)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    Adv,
+    Bxl,
+    Bst,
+    Jnz,
+    Bxc,
+    Out,
+    Bdv,
+    Cdv,
+}
+
+impl Opcode {
+    pub fn from_u8(opcode: u8) -> Option<Self> {
+        Some(match opcode {
+            0 => Opcode::Adv,
+            1 => Opcode::Bxl,
+            2 => Opcode::Bst,
+            3 => Opcode::Jnz,
+            4 => Opcode::Bxc,
+            5 => Opcode::Out,
+            6 => Opcode::Bdv,
+            7 => Opcode::Cdv,
+            _ => return None,
+        })
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Adv => "adv",
+            Opcode::Bxl => "bxl",
+            Opcode::Bst => "bst",
+            Opcode::Jnz => "jnz",
+            Opcode::Bxc => "bxc",
+            Opcode::Out => "out",
+            Opcode::Bdv => "bdv",
+            Opcode::Cdv => "cdv",
+        }
+    }
+
+    pub fn takes_combo(self) -> bool {
+        matches!(
+            self,
+            Opcode::Adv | Opcode::Bst | Opcode::Out | Opcode::Bdv | Opcode::Cdv
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub operand: u8,
+}
+
+fn combo_name(operand: u8) -> String {
+    match operand {
+        0..=3 => operand.to_string(),
+        4 => "A".to_string(),
+        5 => "B".to_string(),
+        6 => "C".to_string(),
+        _ => format!("<invalid {operand}>"),
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.opcode.mnemonic();
+        match self.opcode {
+            // The operand is ignored, but still part of the program.
+            Opcode::Bxc => write!(f, "{mnemonic} ({})", self.operand),
+            opcode if opcode.takes_combo() => write!(f, "{mnemonic} {}", combo_name(self.operand)),
+            _ => write!(f, "{mnemonic} {}", self.operand),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    InvalidOpcode { ip: usize, opcode: u8 },
+    InvalidComboOperand { ip: usize, operand: u8 },
+    MissingOperand { ip: usize },
+    StepLimitExceeded { limit: usize },
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::InvalidOpcode { ip, opcode } => {
+                write!(f, "invalid opcode {opcode} at {ip}")
+            }
+            MachineError::InvalidComboOperand { ip, operand } => {
+                write!(f, "invalid combo operand {operand} at {ip}")
+            }
+            MachineError::MissingOperand { ip } => write!(f, "missing operand at {ip}"),
+            MachineError::StepLimitExceeded { limit } => {
+                write!(f, "program did not halt within {limit} steps")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+impl From<MachineError> for std::io::Error {
+    fn from(err: MachineError) -> Self {
+        std::io::Error::other(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub registers: Registers,
+    pub ip: usize,
+    pub out: Vec<u64>,
+    pub steps: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Halted,
+    Breakpoint(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub ip: usize,
+    pub instruction: Instruction,
+    pub registers: Registers,
+    pub out: Option<u64>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>3}: {:<10} {}",
+            self.ip,
+            self.instruction.to_string(),
+            self.registers
+        )?;
+        if let Some(out) = self.out {
+            write!(f, " out={out}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub registers: Registers,
+    pub program: Vec<u8>,
+}
+
+impl Machine {
+    pub fn new(a: u64, b: u64, c: u64, program: Vec<u8>) -> Self {
+        Self {
+            registers: Registers { a, b, c },
+            program,
+        }
+    }
+
+    pub fn with_registers(&self, registers: Registers) -> Self {
+        Self {
+            registers,
+            program: self.program.clone(),
+        }
+    }
+
+    pub fn initial_state(&self) -> State {
+        State {
+            registers: self.registers,
+            ip: 0,
+            out: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn decode(&self, ip: usize) -> Result<Option<Instruction>, MachineError> {
+        let Some(&opcode) = self.program.get(ip) else {
+            return Ok(None);
+        };
+        let opcode = Opcode::from_u8(opcode).ok_or(MachineError::InvalidOpcode { ip, opcode })?;
+        let &operand = self
+            .program
+            .get(ip + 1)
+            .ok_or(MachineError::MissingOperand { ip })?;
+        Ok(Some(Instruction { opcode, operand }))
+    }
+
+    pub fn disassemble(&self) -> Result<String, MachineError> {
+        let mut out = String::new();
+        for ip in (0..self.program.len()).step_by(2) {
+            if let Some(instruction) = self.decode(ip)? {
+                out.push_str(&format!("{ip:>3}: {instruction}\n"));
+            }
+        }
+        Ok(out)
+    }
+
+    fn combo_operand(ip: usize, operand: u8, registers: Registers) -> Result<u64, MachineError> {
+        match operand {
+            0..=3 => Ok(operand as u64),
+            4 => Ok(registers.a),
+            5 => Ok(registers.b),
+            6 => Ok(registers.c),
+            _ => Err(MachineError::InvalidComboOperand { ip, operand }),
+        }
+    }
+
+    pub fn step(&self, state: &mut State) -> Result<Option<TraceEntry>, MachineError> {
+        let ip = state.ip;
+        let Some(instruction) = self.decode(ip)? else {
+            return Ok(None);
+        };
+
+        let regs = &mut state.registers;
+        let operand = instruction.operand;
+        let combo = || Self::combo_operand(ip, operand, *regs);
+        // A / 2^combo operand, which is 0 once the shift exceeds the width
+        let divide = |a: u64, shift: u64| if shift >= 64 { 0 } else { a >> shift };
+
+        let mut out = None;
+        let mut next_ip = ip + 2;
+        match instruction.opcode {
+            Opcode::Adv => regs.a = divide(regs.a, combo()?),
+            Opcode::Bxl => regs.b ^= operand as u64,
+            Opcode::Bst => regs.b = combo()? % 8,
+            Opcode::Jnz => {
+                if regs.a != 0 {
+                    next_ip = operand as usize;
+                }
+            }
+            Opcode::Bxc => regs.b ^= regs.c,
+            Opcode::Out => out = Some(combo()? % 8),
+            Opcode::Bdv => regs.b = divide(regs.a, combo()?),
+            Opcode::Cdv => regs.c = divide(regs.a, combo()?),
+        }
+
+        state.out.extend(out);
+        state.ip = next_ip;
+        state.steps += 1;
+
+        Ok(Some(TraceEntry {
+            ip,
+            instruction,
+            registers: state.registers,
+            out,
+        }))
+    }
+
+    /// Runs until the program halts or the instruction pointer reaches one of
+    /// the `breakpoints`. The instruction at the current position is always
+    /// executed, so calling this again resumes after a breakpoint.
+    pub fn run_until(
+        &self,
+        state: &mut State,
+        breakpoints: &[usize],
+        max_steps: usize,
+        mut trace: impl FnMut(&TraceEntry),
+    ) -> Result<Stop, MachineError> {
+        loop {
+            if state.steps >= max_steps {
+                return Err(MachineError::StepLimitExceeded { limit: max_steps });
+            }
+            match self.step(state)? {
+                Some(entry) => trace(&entry),
+                None => return Ok(Stop::Halted),
+            }
+            if breakpoints.contains(&state.ip) {
+                return Ok(Stop::Breakpoint(state.ip));
+            }
+        }
+    }
+
+    pub fn run_program(&self) -> Result<Vec<u64>, MachineError> {
+        let mut state = self.initial_state();
+        self.run_until(&mut state, &[], DEFAULT_MAX_STEPS, |_| {})?;
+        Ok(state.out)
+    }
+}
@@ -1,91 +1,14 @@
+mod assembler;
+mod compiled;
+mod machine;
+mod symbolic;
+
+use assembler::{assemble, program_line};
+use compiled::CompiledProgram;
+use machine::{Instruction, Machine, MachineError, Opcode, Registers, Stop, DEFAULT_MAX_STEPS};
 use regex::Regex;
 use std::fs::read_to_string;
-
-#[derive(Debug, Clone, Copy)]
-struct Registers {
-    a: u64,
-    b: u64,
-    c: u64,
-}
-
-#[derive(Debug, Clone)]
-struct Machine {
-    registers: Registers,
-    program: Vec<u8>,
-}
-
-impl Machine {
-    fn new(a: u64, b: u64, c: u64, program: Vec<u8>) -> Self {
-        Self {
-            registers: Registers { a, b, c },
-            program,
-        }
-    }
-
-    fn combo_operand(&self, combo_operand: u8, registers: Registers) -> u64 {
-        match combo_operand {
-            0 | 1 | 2 | 3 => combo_operand as u64,
-            4 => registers.a,
-            5 => registers.b,
-            6 => registers.c,
-            _ => panic!("Invalid combo operand"),
-        }
-    }
-
-    fn run_program(&self) -> Vec<u64> {
-        let mut isp = 0;
-        let mut out = Vec::new();
-
-        let mut regs = self.registers;
-
-        while isp < self.program.len() {
-            let opcode = self.program[isp];
-
-            match opcode {
-                0 => {
-                    // adv
-                    regs.a = regs.a / (1 << self.combo_operand(self.program[isp + 1], regs));
-                }
-                1 => {
-                    // bxl
-                    regs.b ^= self.program[isp + 1] as u64;
-                }
-                2 => {
-                    // bst
-                    regs.b = self.combo_operand(self.program[isp + 1], regs) % 8;
-                }
-                3 => {
-                    // jnz
-                    if regs.a != 0 {
-                        isp = self.program[isp + 1] as usize;
-                        continue;
-                    }
-                }
-                4 => {
-                    // bxc
-                    regs.b ^= regs.c;
-                }
-                5 => {
-                    // out
-                    out.push(self.combo_operand(self.program[isp + 1], regs) % 8);
-                }
-                6 => {
-                    // bdv
-                    regs.b = regs.a / (1 << self.combo_operand(self.program[isp + 1], regs));
-                }
-                7 => {
-                    // cdv
-                    regs.c = regs.a / (1 << self.combo_operand(self.program[isp + 1], regs));
-                }
-                _ => panic!("Invalid opcode"),
-            }
-
-            isp += 2;
-        }
-
-        out
-    }
-}
+use symbolic::symbolic_outputs;
 
 fn read_data(path: &str) -> std::io::Result<Machine> {
     let txt = read_to_string(path)?;
@@ -173,31 +96,67 @@ while A > 0:
 
 Key observation: for one iteration we need to look at the next 10 bits!!!
 => init the next 10 bits
-    
-*/
 
-fn dfs(init_a_reg: u64, idx: usize, program: &Vec<u8>, results: &mut Vec<u64>) {
-    if idx == program.len() {
-        results.push(init_a_reg);
-        return;
-    }
+*/
 
-    for a_3bit in 0..8 {
-        let init_a_reg2 = init_a_reg | (a_3bit << (3 * idx + 7));
+/// Whether the program has the shape the quine search relies on: a single
+/// loop ending in `jnz 0` in which `adv 3` is the only write to A.
+fn has_standard_shape(machine: &Machine) -> Result<bool, MachineError> {
+    let instructions = (0..machine.program.len())
+        .step_by(2)
+        .map(|ip| machine.decode(ip).map(Option::unwrap))
+        .collect::<Result<Vec<Instruction>, _>>()?;
+
+    let ends_with_loop = instructions.last()
+        == Some(&Instruction {
+            opcode: Opcode::Jnz,
+            operand: 0,
+        });
+    let jumps = instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == Opcode::Jnz)
+        .count();
+    let shifts_a: Vec<&Instruction> = instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == Opcode::Adv)
+        .collect();
 
-        let a = init_a_reg2 >> (3 * idx);
+    Ok(ends_with_loop && jumps == 1 && shifts_a.len() == 1 && shifts_a[0].operand == 3)
+}
 
-        let b = a % 8;
-        let b = b ^ 5;
-        let c = a >> b;
-        let b = b ^ 6;
-        let b = b ^ c;
-        let b = b % 8;
+/// Every loop iteration drops the lowest 3 bits of A and the loop ends once A
+/// is 0, so the last output only depends on the highest 3 bits, the second to
+/// last output on the highest 6 bits and so on. A is therefore rebuilt 3 bits
+/// at a time starting from the last output, running the program as a black
+/// box for every candidate.
+fn find_quine_input(machine: &Machine) -> Result<Option<u64>, MachineError> {
+    if !has_standard_shape(machine)? {
+        return Ok(None);
+    }
 
-        if b == program[idx] as u64 {
-            dfs(init_a_reg2, idx + 1, program, results);
+    let program: Vec<u64> = machine.program.iter().map(|&num| num as u64).collect();
+
+    let mut candidates = vec![0u64];
+    for idx in (0..program.len()).rev() {
+        let mut next_candidates = Vec::new();
+        for &a in &candidates {
+            for bits in 0..8 {
+                let Some(a2) = a.checked_mul(8).map(|a| a | bits) else {
+                    continue;
+                };
+                let registers = Registers {
+                    a: a2,
+                    ..machine.registers
+                };
+                if machine.with_registers(registers).run_program()? == program[idx..] {
+                    next_candidates.push(a2);
+                }
+            }
         }
+        candidates = next_candidates;
     }
+
+    Ok(candidates.into_iter().filter(|&a| a != 0).min())
 }
 
 fn main() -> std::io::Result<()> {
@@ -206,7 +165,7 @@ fn main() -> std::io::Result<()> {
 
     let start = std::time::Instant::now();
     let task1 = data
-        .run_program()
+        .run_program()?
         .into_iter()
         .map(|num| num.to_string())
         .collect::<Vec<String>>()
@@ -214,24 +173,87 @@ fn main() -> std::io::Result<()> {
     let duration = start.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
-    let mut results = Vec::new();
-    for a in 0..(1 << 10) {
-        let b = a % 8;
-        let b = b ^ 5;
-        let c = a >> b; // remove the bottom 7 bits
-        let b = b ^ 6;
-        let b = b ^ c;
-        let b = b % 8;
+    let start = std::time::Instant::now();
+    let task2 = find_quine_input(&data)?;
+    let duration = start.elapsed();
+    match task2 {
+        Some(task2) => println!("Task 2: {task2} (took {:?})", duration),
+        None => println!("Task 2: no quine input found (took {:?})", duration),
+    }
 
-        // Key observation: for one iteration we need to look at the next 10 bits!!!
-        // => init the next 10 bits
+    // Debugging aids:
+    // - `cargo run -- disasm` prints the program as mnemonics
+    // - `cargo run -- trace [ip...]` traces the registers per instruction and
+    //   pauses at the given instruction pointers
+    // - `cargo run -- asm <file>` assembles mnemonic source into a `Program:` line
+    // - `cargo run -- symbolic` prints the output digits as formulas of A
+    // - `cargo run -- search <from> <to> [prefix]` brute-forces all values of A
+    //   whose output starts with the prefix (default: the program itself)
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => {}
+        Some("disasm") => print!("{}", data.disassemble()?),
+        Some("trace") => {
+            let breakpoints: Vec<usize> = args[1..]
+                .iter()
+                .map(|ip| ip.parse().expect("Invalid breakpoint"))
+                .collect();
+            let mut state = data.initial_state();
+            loop {
+                let stop =
+                    data.run_until(&mut state, &breakpoints, DEFAULT_MAX_STEPS, |entry| {
+                        println!("{entry}")
+                    })?;
+                match stop {
+                    Stop::Halted => break,
+                    Stop::Breakpoint(ip) => println!("-- breakpoint at {ip}: {}", state.registers),
+                }
+            }
+            println!("Output: {:?}", state.out);
+        }
+        Some("asm") => {
+            let source = read_to_string(args.get(1).expect("Missing source file"))?;
+            println!("{}", program_line(&assemble(&source)?));
+        }
+        Some("symbolic") => {
+            let iterations = symbolic_outputs(&data, data.program.len(), DEFAULT_MAX_STEPS)?;
+            let mut evaluated = Vec::new();
+            for (idx, outputs) in iterations.iter().enumerate() {
+                for out in outputs {
+                    let bits = match out.digit_bits() {
+                        Some((lo, hi)) => format!("A[{lo}..{hi}]"),
+                        None => "constant".to_string(),
+                    };
+                    println!("{idx:>3}: out {out}  <- {bits}");
+                    evaluated.push(out.eval(data.registers.a));
+                }
+            }
 
-        if b == 2 {
-            dfs(a, 1, &data.program, &mut results);
+            // The formulas assume the loop keeps running, so they can only be
+            // compared on the iterations the machine actually executes.
+            let out = data.run_program()?;
+            let n = out.len().min(evaluated.len());
+            println!("Formulas match the machine: {}", out[..n] == evaluated[..n]);
+        }
+        Some("search") => {
+            let from = args.get(1).expect("Missing <from>").parse().unwrap();
+            let to = args.get(2).expect("Missing <to>").parse().unwrap();
+            let prefix: Vec<u8> = match args.get(3) {
+                Some(prefix) => prefix.split(",").map(|num| num.parse().unwrap()).collect(),
+                None => data.program.clone(),
+            };
+
+            let start = std::time::Instant::now();
+            let compiled = CompiledProgram::compile(&data);
+            let matches = compiled.search(from..to, &prefix, DEFAULT_MAX_STEPS);
+            let duration = start.elapsed();
+            println!("{} matches (took {:?})", matches.len(), duration);
+            for &a in matches.iter().take(10) {
+                println!("A={a}: {:?}", compiled.run(a, DEFAULT_MAX_STEPS)?);
+            }
         }
+        Some(arg) => panic!("Unknown argument {arg}"),
     }
-    let task2 = results.into_iter().min().unwrap();
-    println!("Task 2: {task2}");
 
     Ok(())
 }
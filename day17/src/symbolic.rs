@@ -0,0 +1,212 @@
+use crate::machine::{Machine, MachineError, Opcode};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(u64),
+    A,
+    Shr(Box<Expr>, Box<Expr>),
+    Xor(Vec<Expr>),
+    Mod8(Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, a: u64) -> u64 {
+        match self {
+            Expr::Const(value) => *value,
+            Expr::A => a,
+            Expr::Shr(x, s) => match s.eval(a) {
+                s if s >= 64 => 0,
+                s => x.eval(a) >> s,
+            },
+            Expr::Xor(operands) => operands.iter().fold(0, |acc, x| acc ^ x.eval(a)),
+            Expr::Mod8(x) => x.eval(a) % 8,
+        }
+    }
+
+    pub fn max_value(&self) -> Option<u64> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            Expr::A => None,
+            Expr::Shr(x, _) => x.max_value(),
+            Expr::Xor(operands) => operands.iter().try_fold(0, |acc, x| {
+                // XOR never sets bits above the highest bit of its operands
+                let max = acc | x.max_value()?;
+                Some(u64::MAX >> max.leading_zeros())
+            }),
+            Expr::Mod8(_) => Some(7),
+        }
+    }
+
+    /// Bits `lo..hi` of A which can influence bits `window` of the value,
+    /// `None` if A does not influence them at all.
+    fn influence(&self, window: (u32, u32)) -> Option<(u32, u32)> {
+        let (lo, hi) = window;
+        if lo >= hi {
+            return None;
+        }
+        match self {
+            Expr::Const(_) => None,
+            Expr::A => Some((lo, hi.min(64))),
+            Expr::Shr(x, s) => {
+                let s_min = match s.as_ref() {
+                    Expr::Const(value) => *value as u32,
+                    _ => 0,
+                };
+                let s_max = s.max_value().map_or(64, |max| max.min(64) as u32);
+                let shifted = x.influence((
+                    lo.saturating_add(s_min).min(64),
+                    hi.saturating_add(s_max).min(64),
+                ));
+                // Only the lowest 7 bits of the shift amount matter.
+                union(shifted, s.influence((0, 7)))
+            }
+            Expr::Xor(operands) => operands
+                .iter()
+                .fold(None, |acc, x| union(acc, x.influence(window))),
+            Expr::Mod8(x) => x.influence((lo, hi.min(3))),
+        }
+    }
+
+    pub fn digit_bits(&self) -> Option<(u32, u32)> {
+        self.influence((0, 3))
+    }
+
+    pub fn simplify(self) -> Expr {
+        match self {
+            Expr::Const(_) | Expr::A => self,
+            Expr::Shr(x, s) => match (x.simplify(), s.simplify()) {
+                (Expr::Const(x), Expr::Const(s)) => Expr::Const(if s >= 64 { 0 } else { x >> s }),
+                (x, Expr::Const(0)) => x,
+                (Expr::Shr(x, inner), Expr::Const(s2)) if matches!(*inner, Expr::Const(_)) => {
+                    let Expr::Const(s1) = *inner else {
+                        unreachable!()
+                    };
+                    Expr::Shr(x, Box::new(Expr::Const(s1 + s2)))
+                }
+                (x, s) => Expr::Shr(Box::new(x), Box::new(s)),
+            },
+            Expr::Xor(operands) => {
+                // Flatten, fold constants and cancel out equal operands.
+                let mut constant = 0;
+                let mut terms: Vec<Expr> = Vec::new();
+                let mut stack: Vec<Expr> = operands.into_iter().map(Expr::simplify).collect();
+                while let Some(x) = stack.pop() {
+                    match x {
+                        Expr::Const(value) => constant ^= value,
+                        Expr::Xor(inner) => stack.extend(inner),
+                        x => match terms.iter().position(|term| *term == x) {
+                            Some(idx) => {
+                                terms.remove(idx);
+                            }
+                            None => terms.push(x),
+                        },
+                    }
+                }
+                terms.reverse();
+                if constant != 0 || terms.is_empty() {
+                    terms.push(Expr::Const(constant));
+                }
+                match terms.len() {
+                    1 => terms.pop().unwrap(),
+                    _ => Expr::Xor(terms),
+                }
+            }
+            Expr::Mod8(x) => match x.simplify() {
+                Expr::Const(value) => Expr::Const(value % 8),
+                x if x.max_value().is_some_and(|max| max < 8) => x,
+                Expr::Xor(operands) => Expr::Xor(
+                    operands
+                        .into_iter()
+                        .map(|x| Expr::Mod8(Box::new(x)))
+                        .collect(),
+                )
+                .simplify(),
+                x => Expr::Mod8(Box::new(x)),
+            },
+        }
+    }
+}
+
+fn union(a: Option<(u32, u32)>, b: Option<(u32, u32)>) -> Option<(u32, u32)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.max(b.1))),
+        (a, b) => a.or(b),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{value}"),
+            Expr::A => write!(f, "A"),
+            Expr::Shr(x, s) => write!(f, "({x} >> {s})"),
+            Expr::Xor(operands) => {
+                let operands: Vec<String> = operands.iter().map(Expr::to_string).collect();
+                write!(f, "({})", operands.join(" ^ "))
+            }
+            Expr::Mod8(x) => write!(f, "({x} % 8)"),
+        }
+    }
+}
+
+/// Runs the program on a symbolic register A. While A is symbolic, every
+/// `jnz` is assumed to jump for the first `iterations` times and to fall
+/// through afterwards. Returns the simplified outputs per loop iteration.
+pub fn symbolic_outputs(
+    machine: &Machine,
+    iterations: usize,
+    max_steps: usize,
+) -> Result<Vec<Vec<Expr>>, MachineError> {
+    let mut a = Expr::A;
+    let mut b = Expr::Const(machine.registers.b);
+    let mut c = Expr::Const(machine.registers.c);
+
+    let mut outputs = vec![Vec::new()];
+    let mut ip = 0;
+    let mut steps = 0;
+
+    while let Some(instruction) = machine.decode(ip)? {
+        if steps >= max_steps {
+            return Err(MachineError::StepLimitExceeded { limit: max_steps });
+        }
+        steps += 1;
+
+        let operand = instruction.operand;
+        let combo = |a: &Expr, b: &Expr, c: &Expr| match operand {
+            0..=3 => Ok(Expr::Const(operand as u64)),
+            4 => Ok(a.clone()),
+            5 => Ok(b.clone()),
+            6 => Ok(c.clone()),
+            _ => Err(MachineError::InvalidComboOperand { ip, operand }),
+        };
+        let shr = |x: &Expr, s: Expr| Expr::Shr(Box::new(x.clone()), Box::new(s)).simplify();
+
+        let mut next_ip = ip + 2;
+        match instruction.opcode {
+            Opcode::Adv => a = shr(&a, combo(&a, &b, &c)?),
+            Opcode::Bxl => b = Expr::Xor(vec![b, Expr::Const(operand as u64)]).simplify(),
+            Opcode::Bst => b = Expr::Mod8(Box::new(combo(&a, &b, &c)?)).simplify(),
+            Opcode::Jnz => {
+                let jump = match a {
+                    Expr::Const(value) => value != 0,
+                    _ => outputs.len() < iterations,
+                };
+                if jump {
+                    next_ip = operand as usize;
+                    outputs.push(Vec::new());
+                }
+            }
+            Opcode::Bxc => b = Expr::Xor(vec![b, c.clone()]).simplify(),
+            Opcode::Out => outputs
+                .last_mut()
+                .unwrap()
+                .push(Expr::Mod8(Box::new(combo(&a, &b, &c)?)).simplify()),
+            Opcode::Bdv => b = shr(&a, combo(&a, &b, &c)?),
+            Opcode::Cdv => c = shr(&a, combo(&a, &b, &c)?),
+        }
+        ip = next_ip;
+    }
+
+    Ok(outputs)
+}
@@ -1,4 +1,5 @@
-use std::fs::read_to_string;
+use std::fs::{self, read_to_string};
+use std::path::Path;
 
 fn read_data(path: &str) -> std::io::Result<Vec<Vec<usize>>> {
     Ok(read_to_string(path)?
@@ -11,49 +12,236 @@ fn read_data(path: &str) -> std::io::Result<Vec<Vec<usize>>> {
         .collect())
 }
 
-const EMPTY: u8 = 0;
-const BYTE: u8 = 1;
-const VISITED: u8 = 2;
-
-fn find_path(bytes: &Vec<Vec<usize>>, h: usize, w: usize, n_bytes: usize) -> usize {
-    let mut field = vec![vec![EMPTY; w]; h];
-    for idx in 0..n_bytes {
-        field[bytes[idx][0]][bytes[idx][1]] = BYTE;
+/// `is_free(x, y, time)` tells whether the cell can be entered at `time`,
+/// the number of steps taken when arriving there.
+fn bfs(
+    h: usize,
+    w: usize,
+    is_free: impl Fn(usize, usize, usize) -> bool,
+) -> Option<Vec<(usize, usize)>> {
+    if !is_free(0, 0, 0) {
+        return None;
     }
 
+    let mut parents = vec![vec![None; w]; h];
+    let mut visited = vec![vec![false; w]; h];
     let mut queue = vec![(0usize, 0usize)];
-    field[0][0] = VISITED;
-    let mut steps = 0;
+    visited[0][0] = true;
+    let mut time = 0;
 
     while !queue.is_empty() {
         let mut next = Vec::new();
 
         for (x, y) in queue {
             if (x, y) == (h - 1, w - 1) {
-                return steps;
+                let mut path = vec![(x, y)];
+                let mut current = (x, y);
+                while let Some(parent) = parents[current.0][current.1] {
+                    path.push(parent);
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
             }
 
             for (ox, oy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
-                let x2 = x as i32 + ox;
-                let y2 = y as i32 + oy;
-
-                if 0 <= x2
-                    && x2 < (h as i32)
-                    && 0 <= y2
-                    && y2 < (w as i32)
-                    && field[x2 as usize][y2 as usize] == EMPTY
-                {
-                    next.push((x2 as usize, y2 as usize));
-                    field[x2 as usize][y2 as usize] = VISITED;
+                let (Some(x2), Some(y2)) = (x.checked_add_signed(ox), y.checked_add_signed(oy))
+                else {
+                    continue;
+                };
+
+                if x2 < h && y2 < w && !visited[x2][y2] && is_free(x2, y2, time + 1) {
+                    next.push((x2, y2));
+                    visited[x2][y2] = true;
+                    parents[x2][y2] = Some((x, y));
                 }
             }
         }
 
         queue = next;
-        steps += 1;
+        time += 1;
+    }
+
+    None
+}
+
+fn shortest_path(
+    bytes: &[Vec<usize>],
+    h: usize,
+    w: usize,
+    n_bytes: usize,
+) -> Option<Vec<(usize, usize)>> {
+    let mut corrupted = vec![vec![false; w]; h];
+    for byte in &bytes[..n_bytes] {
+        corrupted[byte[0]][byte[1]] = true;
+    }
+    bfs(h, w, |x, y, _| !corrupted[x][y])
+}
+
+fn find_path(bytes: &[Vec<usize>], h: usize, w: usize, n_bytes: usize) -> usize {
+    shortest_path(bytes, h, w, n_bytes).map_or(usize::MAX, |path| path.len() - 1)
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+struct Blocking {
+    index: usize,
+    last_path: Vec<(usize, usize)>,
+}
+
+/// Starts with all bytes fallen and removes them in reverse order, joining
+/// the freed cell with its free neighbours in a union-find. The byte whose
+/// removal first connects both corners is the blocking one.
+fn find_blocking_byte(bytes: &[Vec<usize>], h: usize, w: usize) -> Option<Blocking> {
+    let cell = |x: usize, y: usize| x * w + y;
+
+    // A cell becomes free again once its first byte is removed.
+    let mut first_byte = vec![usize::MAX; h * w];
+    for (idx, byte) in bytes.iter().enumerate().rev() {
+        first_byte[cell(byte[0], byte[1])] = idx;
+    }
+
+    let mut free: Vec<bool> = first_byte.iter().map(|&idx| idx == usize::MAX).collect();
+    let mut components = UnionFind::new(h * w);
+    let join_neighbours = |components: &mut UnionFind, free: &[bool], x: usize, y: usize| {
+        for (ox, oy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (Some(x2), Some(y2)) = (x.checked_add_signed(ox), y.checked_add_signed(oy)) else {
+                continue;
+            };
+            if x2 < h && y2 < w && free[cell(x2, y2)] {
+                components.union(cell(x, y), cell(x2, y2));
+            }
+        }
+    };
+
+    for x in 0..h {
+        for y in 0..w {
+            if free[cell(x, y)] {
+                join_neighbours(&mut components, &free, x, y);
+            }
+        }
+    }
+
+    let (start, end) = (cell(0, 0), cell(h - 1, w - 1));
+    let connected = |components: &mut UnionFind, free: &[bool]| {
+        free[start] && free[end] && components.find(start) == components.find(end)
+    };
+    if connected(&mut components, &free) {
+        // All bytes fallen and the exit is still reachable
+        return None;
+    }
+
+    for idx in (0..bytes.len()).rev() {
+        let (x, y) = (bytes[idx][0], bytes[idx][1]);
+        if first_byte[cell(x, y)] != idx {
+            continue;
+        }
+        free[cell(x, y)] = true;
+        join_neighbours(&mut components, &free, x, y);
+
+        if connected(&mut components, &free) {
+            return Some(Blocking {
+                index: idx,
+                last_path: shortest_path(bytes, h, w, idx)?,
+            });
+        }
     }
 
-    usize::MAX
+    None
+}
+
+/// Byte `i` corrupts its cell at time `i`. Cells never become free again, so
+/// arriving somewhere earlier is always at least as good as arriving later.
+/// Therefore waiting never helps and a BFS which checks the arrival time at
+/// every cell is enough.
+fn timed_path(bytes: &[Vec<usize>], h: usize, w: usize) -> Option<Vec<(usize, usize)>> {
+    let mut corrupted_at = vec![vec![usize::MAX; w]; h];
+    for (idx, byte) in bytes.iter().enumerate().rev() {
+        corrupted_at[byte[0]][byte[1]] = idx;
+    }
+    bfs(h, w, |x, y, time| corrupted_at[x][y] > time)
+}
+
+fn render(bytes: &[Vec<usize>], h: usize, w: usize, n_bytes: usize) -> String {
+    let mut field = vec![vec!['.'; w]; h];
+    for byte in &bytes[..n_bytes] {
+        field[byte[0]][byte[1]] = '#';
+    }
+    for (x, y) in shortest_path(bytes, h, w, n_bytes).unwrap_or_default() {
+        field[x][y] = 'O';
+    }
+
+    let mut out = String::new();
+    for y in 0..w {
+        out.extend(field.iter().map(|column| column[y]));
+        out.push('\n');
+    }
+    out
+}
+
+/// Only bytes which fall onto the current shortest path change it, so only
+/// those get a frame.
+fn export_animation(
+    bytes: &[Vec<usize>],
+    h: usize,
+    w: usize,
+    dir: &Path,
+) -> std::io::Result<usize> {
+    fs::create_dir_all(dir)?;
+
+    let end = find_blocking_byte(bytes, h, w).map_or(bytes.len(), |blocking| blocking.index + 1);
+    let mut path = Vec::new();
+    let mut frames = 0;
+
+    for n_bytes in 0..=end {
+        // The path only changes once one of its cells is corrupted.
+        let cut = n_bytes == 0 || {
+            let byte = &bytes[n_bytes - 1];
+            path.contains(&(byte[0], byte[1]))
+        };
+        if cut {
+            path = shortest_path(bytes, h, w, n_bytes).unwrap_or_default();
+            fs::write(
+                dir.join(format!("frame_{n_bytes:05}.txt")),
+                render(bytes, h, w, n_bytes),
+            )?;
+            frames += 1;
+        }
+    }
+
+    Ok(frames)
 }
 
 fn main() -> std::io::Result<()> {
@@ -66,11 +254,44 @@ fn main() -> std::io::Result<()> {
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start = std::time::Instant::now();
-    let idxs = (0..=bytes.len()).collect::<Vec<usize>>();
-    let target_idx = idxs.partition_point(|&n_bytes| find_path(&bytes, h, w, n_bytes) < usize::MAX);
-    let task2 = format!("{},{}", bytes[target_idx - 1][0], bytes[target_idx - 1][1]);
+    let blocking = find_blocking_byte(&bytes, h, w).expect("The exit is never blocked");
+    let task2 = format!("{},{}", bytes[blocking.index][0], bytes[blocking.index][1]);
     let duration = start.elapsed();
     println!("Task 2: {task2} (took {:?})", duration);
 
+    // Debugging aids:
+    // - `cargo run -- render <n>` shows the memory space after n bytes
+    // - `cargo run -- export <dir>` writes a frame whenever the path is cut
+    // - `cargo run -- blocking` shows the last path before the exit is cut off
+    // - `cargo run -- timed` walks to the exit while the bytes keep falling
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {}
+        ["render", n] => print!(
+            "{}",
+            render(&bytes, h, w, n.parse().expect("Invalid byte count"))
+        ),
+        ["export", dir] => {
+            let frames = export_animation(&bytes, h, w, Path::new(dir))?;
+            println!("Wrote {frames} frames to {dir}");
+        }
+        ["blocking"] => {
+            print!("{}", render(&bytes, h, w, blocking.index));
+            println!(
+                "Blocked by byte {}, last path had {} steps",
+                blocking.index,
+                blocking.last_path.len() - 1
+            );
+        }
+        ["timed"] => match timed_path(&bytes, h, w) {
+            Some(path) => println!(
+                "Exit reached at time {} while bytes are falling",
+                path.len() - 1
+            ),
+            None => println!("Exit unreachable while bytes are falling"),
+        },
+        _ => panic!("Expected `render <n>`, `export <dir>`, `blocking` or `timed`"),
+    }
+
     Ok(())
 }
@@ -1,95 +1,461 @@
-use std::collections::HashSet;
+use rand::Rng;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_to_string;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
-#[repr(u8)]
-enum Color {
-    White,
-    Blue,
-    Black,
-    Red,
-    Green,
+type Symbol = u32;
+
+/// Characters of the input, towels and designs are stored as indices into it.
+struct Alphabet {
+    chars: Vec<char>,
+    symbols: HashMap<char, Symbol>,
 }
 
-impl Color {
-    fn from_char(c: char) -> Self {
-        match c {
-            'w' => Self::White,
-            'u' => Self::Blue,
-            'b' => Self::Black,
-            'r' => Self::Red,
-            'g' => Self::Green,
-            _ => panic!("Invalid color"),
+impl Alphabet {
+    fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut alphabet = Alphabet {
+            chars: Vec::new(),
+            symbols: HashMap::new(),
+        };
+        for c in words.into_iter().flat_map(str::chars) {
+            alphabet.symbols.entry(c).or_insert_with(|| {
+                alphabet.chars.push(c);
+                (alphabet.chars.len() - 1) as Symbol
+            });
         }
+        alphabet
+    }
+
+    fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    fn encode(&self, word: &str) -> Option<Stripes> {
+        word.chars()
+            .map(|c| self.symbols.get(&c).copied())
+            .collect()
+    }
+
+    fn decode(&self, stripes: &[Symbol]) -> String {
+        stripes
+            .iter()
+            .map(|&symbol| self.chars[symbol as usize])
+            .collect()
     }
 }
 
-fn read_data(path: &str) -> std::io::Result<(Vec<Vec<Color>>, Vec<Vec<Color>>)> {
+type Stripes = Vec<Symbol>;
+
+fn read_data(path: &str) -> std::io::Result<(Alphabet, Vec<Stripes>, Vec<Stripes>)> {
     let data = read_to_string(path)?;
     let mut iter = data.split("\n");
 
-    let patterns = iter.next().unwrap();
-    let patterns = patterns
-        .split(", ")
-        .map(|pattern| pattern.chars().map(Color::from_char).collect())
+    let patterns: Vec<&str> = iter.next().unwrap().split(", ").collect();
+    iter.next();
+    let queries: Vec<&str> = iter.collect();
+
+    let alphabet = Alphabet::new(patterns.iter().chain(&queries).copied());
+    let encode = |words: Vec<&str>| -> Vec<Stripes> {
+        words
+            .into_iter()
+            .map(|word| alphabet.encode(word).unwrap())
+            .collect()
+    };
+    let (patterns, queries) = (encode(patterns), encode(queries));
+
+    Ok((alphabet, patterns, queries))
+}
+
+type Arrangement<'a> = Vec<&'a [Symbol]>;
+
+fn format_arrangement(alphabet: &Alphabet, arrangement: &Arrangement) -> String {
+    let towels: Vec<String> = arrangement
+        .iter()
+        .map(|towel| alphabet.decode(towel))
         .collect();
+    towels.join(" + ")
+}
 
-    iter.next();
+/// Towel patterns looked up by length, the original approach which is kept as
+/// a baseline for `cargo run --release -- bench`.
+struct HashSetMatcher {
+    lookup: HashSet<Stripes>,
+    max_length: usize,
+}
+
+impl HashSetMatcher {
+    fn new(patterns: &[Stripes]) -> Self {
+        Self {
+            lookup: patterns.iter().cloned().collect(),
+            max_length: patterns
+                .iter()
+                .map(|pattern| pattern.len())
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    fn count_arrangements(&self, query: &[Symbol]) -> u128 {
+        let mut dp = vec![0u128; query.len()];
+
+        for idx in 0..query.len() {
+            if idx > 0 && dp[idx - 1] == 0 {
+                continue;
+            }
+
+            // found match for [0..idx-1], now try to find a
+            // match starting at idx
+            let mut pattern = Vec::with_capacity(self.max_length);
+            for idx2 in idx..std::cmp::min(query.len(), idx + self.max_length) {
+                pattern.push(query[idx2]);
+                if self.lookup.contains(&pattern) {
+                    dp[idx2] += if idx > 0 { dp[idx - 1] } else { 1 };
+                }
+            }
+        }
 
-    let mut queries = Vec::new();
-    while let Some(query) = iter.next() {
-        queries.push(query.chars().map(Color::from_char).collect());
+        dp[query.len() - 1]
     }
+}
 
-    Ok((patterns, queries))
+struct Trie {
+    n_symbols: usize,
+    /// Child of node `n` for symbol `s` at `n * n_symbols + s`, 0 if there is
+    /// none (the root is never a child).
+    children: Vec<u32>,
+    is_pattern: Vec<bool>,
 }
 
-fn count_arrangements(
-    pattern_lookup: &HashSet<Vec<Color>>,
-    pattern_max_length: usize,
-    query: &Vec<Color>,
-) -> i64 {
-    let mut dp = vec![0i64; query.len()];
+impl Trie {
+    const ROOT: usize = 0;
+
+    fn new(patterns: &[Stripes], n_symbols: usize) -> Self {
+        let mut trie = Self {
+            n_symbols,
+            children: Vec::new(),
+            is_pattern: Vec::new(),
+        };
+        trie.add_node();
+        for pattern in patterns {
+            trie.insert(pattern);
+        }
+        trie
+    }
+
+    fn add_node(&mut self) -> usize {
+        self.children.extend(std::iter::repeat_n(0, self.n_symbols));
+        self.is_pattern.push(false);
+        self.is_pattern.len() - 1
+    }
+
+    fn child(&self, node: usize, symbol: Symbol) -> Option<usize> {
+        match self.children[node * self.n_symbols + symbol as usize] {
+            0 => None,
+            child => Some(child as usize),
+        }
+    }
+
+    fn insert(&mut self, pattern: &[Symbol]) {
+        let mut node = Self::ROOT;
+        for &symbol in pattern {
+            node = match self.child(node, symbol) {
+                Some(child) => child,
+                None => {
+                    let child = self.add_node();
+                    self.children[node * self.n_symbols + symbol as usize] = child as u32;
+                    child
+                }
+            };
+        }
+        self.is_pattern[node] = true;
+    }
+
+    fn prefix_lengths<'a>(&'a self, query: &'a [Symbol]) -> impl Iterator<Item = usize> + 'a {
+        let mut node = Self::ROOT;
+        query
+            .iter()
+            .map_while(move |&symbol| {
+                node = self.child(node, symbol)?;
+                Some(self.is_pattern[node])
+            })
+            .enumerate()
+            .filter_map(|(idx, is_pattern)| is_pattern.then_some(idx + 1))
+    }
+
+    /// `counts[idx]` is the number of arrangements of `query[idx..]`, so
+    /// `counts[0]` counts the whole design.
+    fn suffix_counts(&self, query: &[Symbol]) -> Vec<u128> {
+        let mut counts = vec![0u128; query.len() + 1];
+        counts[query.len()] = 1;
+
+        for idx in (0..query.len()).rev() {
+            counts[idx] = self
+                .prefix_lengths(&query[idx..])
+                .map(|length| counts[idx + length])
+                .sum();
+        }
+
+        counts
+    }
+
+    fn count_arrangements(&self, query: &[Symbol]) -> u128 {
+        self.suffix_counts(query)[0]
+    }
+
+    fn arrangements<'a>(&'a self, query: &'a [Symbol]) -> Arrangements<'a> {
+        let mut arrangements = Arrangements {
+            trie: self,
+            query,
+            counts: self.suffix_counts(query),
+            towels: Vec::new(),
+            position: 0,
+            candidates: Vec::new(),
+        };
+        if arrangements.counts[0] > 0 {
+            let first = arrangements.towel_lengths(0);
+            arrangements.candidates.push(first);
+        }
+        arrangements
+    }
+
+    fn fewest_towels<'a>(&self, query: &'a [Symbol]) -> Option<Arrangement<'a>> {
+        // best[idx] is the fewest towels for `query[..idx]` and the
+        // position the last of them starts at.
+        let mut best: Vec<Option<(usize, usize)>> = vec![None; query.len() + 1];
+        best[0] = Some((0, 0));
+
+        for idx in 0..query.len() {
+            let Some((n_towels, _)) = best[idx] else {
+                continue;
+            };
+            for length in self.prefix_lengths(&query[idx..]) {
+                let next = &mut best[idx + length];
+                if next.is_none_or(|(other, _)| n_towels + 1 < other) {
+                    *next = Some((n_towels + 1, idx));
+                }
+            }
+        }
+
+        best[query.len()]?;
+        let mut arrangement = Vec::new();
+        let mut end = query.len();
+        while end > 0 {
+            let (_, start) = best[end].unwrap();
+            arrangement.push(&query[start..end]);
+            end = start;
+        }
+        arrangement.reverse();
+        Some(arrangement)
+    }
 
-    for idx in 0..query.len() {
-        if idx > 0 && dp[idx - 1] == 0 {
-            continue;
+    /// Draws one of the arrangements of `query` uniformly at random, `None` if
+    /// there is none.
+    ///
+    /// Every towel is picked with a probability proportional to the number
+    /// of ways to complete the design after it.
+    fn sample_arrangement<'a>(
+        &self,
+        query: &'a [Symbol],
+        rng: &mut impl Rng,
+    ) -> Option<Arrangement<'a>> {
+        let counts = self.suffix_counts(query);
+        if counts[0] == 0 {
+            return None;
         }
 
-        // found match for [0..idx-1], now try to find a
-        // match starting at idx
-        let mut pattern = Vec::with_capacity(pattern_max_length);
-        for idx2 in idx..std::cmp::min(query.len(), idx + pattern_max_length) {
-            pattern.push(query[idx2]);
-            if pattern_lookup.contains(&pattern) {
-                dp[idx2] += if idx > 0 { dp[idx - 1] } else { 1 };
+        let mut arrangement = Vec::new();
+        let mut idx = 0;
+        while idx < query.len() {
+            let mut pick = rng.gen_range(0..counts[idx]);
+            let length = self
+                .prefix_lengths(&query[idx..])
+                .find(|&length| {
+                    let found = pick < counts[idx + length];
+                    pick = pick.saturating_sub(counts[idx + length]);
+                    found
+                })
+                .unwrap();
+            arrangement.push(&query[idx..idx + length]);
+            idx += length;
+        }
+        Some(arrangement)
+    }
+}
+
+/// Depth-first enumeration of arrangements which never enters a dead end,
+/// since only towels after which the design can be completed are tried.
+struct Arrangements<'a> {
+    trie: &'a Trie,
+    query: &'a [Symbol],
+    counts: Vec<u128>,
+    towels: Vec<usize>,
+    position: usize,
+    /// Towel lengths left to try, one entry per placed towel plus one
+    candidates: Vec<Vec<usize>>,
+}
+
+impl Arrangements<'_> {
+    fn towel_lengths(&self, idx: usize) -> Vec<usize> {
+        let mut lengths: Vec<usize> = self
+            .trie
+            .prefix_lengths(&self.query[idx..])
+            .filter(|&length| self.counts[idx + length] > 0)
+            .collect();
+        lengths.reverse();
+        lengths
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Arrangement<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(length) = self.candidates.last_mut()?.pop() else {
+                self.candidates.pop();
+                if let Some(length) = self.towels.pop() {
+                    self.position -= length;
+                }
+                continue;
+            };
+
+            self.towels.push(length);
+            self.position += length;
+
+            if self.position == self.query.len() {
+                let mut start = 0;
+                let arrangement = self
+                    .towels
+                    .iter()
+                    .map(|&length| {
+                        start += length;
+                        &self.query[start - length..start]
+                    })
+                    .collect();
+                self.towels.pop();
+                self.position -= length;
+                return Some(arrangement);
             }
+
+            let next = self.towel_lengths(self.position);
+            self.candidates.push(next);
         }
     }
+}
 
-    dp[query.len() - 1]
+fn explain(alphabet: &Alphabet, trie: &Trie, design: &str, limit: usize) {
+    let Some(query) = alphabet.encode(design) else {
+        println!("{design}: contains characters which no towel has");
+        return;
+    };
+
+    let count = trie.count_arrangements(&query);
+    println!("{design}: {count} arrangements");
+    let Some(fewest) = trie.fewest_towels(&query) else {
+        return;
+    };
+    println!(
+        "Fewest towels ({}): {}",
+        fewest.len(),
+        format_arrangement(alphabet, &fewest)
+    );
+    let sample = trie
+        .sample_arrangement(&query, &mut rand::thread_rng())
+        .unwrap();
+    println!("Random: {}", format_arrangement(alphabet, &sample));
+    for arrangement in trie.arrangements(&query).take(limit) {
+        println!("  {}", format_arrangement(alphabet, &arrangement));
+    }
+    if count > limit as u128 {
+        println!("  ...");
+    }
+}
+
+fn benchmark(alphabet: &Alphabet, patterns: &[Stripes], queries: &[Stripes], rounds: u32) {
+    let start = std::time::Instant::now();
+    let hash_set = HashSetMatcher::new(patterns);
+    let mut expected = 0;
+    for _ in 0..rounds {
+        expected = queries
+            .iter()
+            .map(|query| hash_set.count_arrangements(query))
+            .sum::<u128>();
+    }
+    println!(
+        "Hash set: {expected} (took {:?} per round)",
+        start.elapsed() / rounds
+    );
+
+    let start = std::time::Instant::now();
+    let trie = Trie::new(patterns, alphabet.len());
+    let mut total = 0;
+    for _ in 0..rounds {
+        total = queries
+            .iter()
+            .map(|query| trie.count_arrangements(query))
+            .sum::<u128>();
+    }
+    println!(
+        "Trie: {total} (took {:?} per round)",
+        start.elapsed() / rounds
+    );
+
+    let start = std::time::Instant::now();
+    for _ in 0..rounds {
+        total = queries
+            .par_iter()
+            .map(|query| trie.count_arrangements(query))
+            .sum::<u128>();
+    }
+    println!(
+        "Parallel trie: {total} (took {:?} per round)",
+        start.elapsed() / rounds
+    );
+
+    assert_eq!(expected, total, "Matchers disagree");
 }
 
 fn main() -> std::io::Result<()> {
-    // let (patterns, queries) = read_data("example.txt")?;
-    let (patterns, queries) = read_data("input.txt")?;
+    // let (alphabet, patterns, queries) = read_data("example.txt")?;
+    let (alphabet, patterns, queries) = read_data("input.txt")?;
 
-    let pattern_max_length = patterns.iter().map(|pattern| pattern.len()).max().unwrap();
-    let pattern_lookup = patterns.into_iter().collect::<HashSet<Vec<Color>>>();
+    let trie = Trie::new(&patterns, alphabet.len());
+
+    // - `cargo run --release -- bench [rounds]` compares the matchers
+    // - `cargo run -- explain <design> [limit]` lists arrangements of a design
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {}
+        ["bench", ref rest @ ..] => {
+            let rounds = rest
+                .first()
+                .map_or(10, |rounds| rounds.parse().expect("Invalid rounds"));
+            benchmark(&alphabet, &patterns, &queries, rounds);
+            return Ok(());
+        }
+        ["explain", design, ref rest @ ..] => {
+            let limit = rest
+                .first()
+                .map_or(10, |limit| limit.parse().expect("Invalid limit"));
+            explain(&alphabet, &trie, design, limit);
+            return Ok(());
+        }
+        _ => panic!("Expected `bench [rounds]` or `explain <design> [limit]`"),
+    }
 
     let start = std::time::Instant::now();
     let task1 = queries
-        .iter()
-        .filter(|query| count_arrangements(&pattern_lookup, pattern_max_length, *query) > 0)
+        .par_iter()
+        .filter(|query| trie.count_arrangements(query) > 0)
         .count();
     let duration = start.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start = std::time::Instant::now();
     let task2 = queries
-        .iter()
-        .map(|query| count_arrangements(&pattern_lookup, pattern_max_length, query))
-        .sum::<i64>();
+        .par_iter()
+        .map(|query| trie.count_arrangements(query))
+        .sum::<u128>();
     let duration = start.elapsed();
     println!("Task 2: {task2} (took {:?})", duration);
 
@@ -1,5 +1,5 @@
 use rayon::prelude::*;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fs::read_to_string;
 
 fn read_data(path: &str) -> std::io::Result<Vec<Vec<char>>> {
@@ -9,25 +9,16 @@ fn read_data(path: &str) -> std::io::Result<Vec<Vec<char>>> {
         .collect())
 }
 
-fn calculate_path(
-    field: &Vec<Vec<char>>,
-    start: (usize, usize),
-    end: (usize, usize),
-) -> Vec<(usize, usize)> {
-    let mut visited = vec![vec![false; field[0].len()]; field.len()];
-    let mut path = Vec::new();
+fn distances(field: &[Vec<char>], start: (usize, usize)) -> Vec<Vec<Option<usize>>> {
+    let mut dist = vec![vec![None; field[0].len()]; field.len()];
 
     let mut queue = VecDeque::new();
     queue.push_back(start);
 
-    visited[start.0][start.1] = true;
+    dist[start.0][start.1] = Some(0);
 
     while let Some((x, y)) = queue.pop_front() {
-        path.push((x, y));
-
-        if (x, y) == end {
-            break;
-        }
+        let d = dist[x][y].unwrap();
 
         for (ox, oy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
             let x2 = x as i32 + ox;
@@ -37,51 +28,96 @@ fn calculate_path(
                 let x2 = x2 as usize;
                 let y2 = y2 as usize;
 
-                if !visited[x2][y2] && field[x2][y2] != '#' {
+                if dist[x2][y2].is_none() && field[x2][y2] != '#' {
                     queue.push_back((x2, y2));
-                    visited[x2][y2] = true;
+                    dist[x2][y2] = Some(d + 1);
                 }
             }
         }
     }
 
-    path
+    dist
 }
 
-fn l1_distance(p1: (usize, usize), p2: (usize, usize)) -> usize {
-    ((p1.0 as i64 - p2.0 as i64).abs() + (p1.1 as i64 - p2.1 as i64).abs()) as usize
+/// For every track cell, only the cells within L1 distance `max_cheat` are
+/// scanned, so this takes O(n * max_cheat^2) for n track cells.
+fn cheat_histogram(
+    field: &[Vec<char>],
+    start: (usize, usize),
+    max_cheat: usize,
+) -> BTreeMap<usize, usize> {
+    let dist = distances(field, start);
+    let track: Vec<(usize, usize, usize)> = dist
+        .iter()
+        .enumerate()
+        .flat_map(|(x, row)| {
+            row.iter()
+                .enumerate()
+                .filter_map(move |(y, d)| d.map(|d| (x, y, d)))
+        })
+        .collect();
+
+    let radius = max_cheat as i64;
+    let (h, w) = (field.len() as i64, field[0].len() as i64);
+
+    // Savings are below the number of track cells, so count them in a
+    // vector and only build the map at the end.
+    let counts = track
+        .par_iter()
+        .fold(
+            || vec![0usize; track.len()],
+            |mut counts, &(x, y, d)| {
+                for ox in -radius..=radius {
+                    let x2 = x as i64 + ox;
+                    if x2 < 0 || x2 >= h {
+                        continue;
+                    }
+                    let remaining = radius - ox.abs();
+                    for oy in -remaining..=remaining {
+                        let y2 = y as i64 + oy;
+                        if y2 < 0 || y2 >= w {
+                            continue;
+                        }
+                        let Some(d2) = dist[x2 as usize][y2 as usize] else {
+                            continue;
+                        };
+                        let cheat_dist = (ox.abs() + oy.abs()) as usize;
+                        if d2 > d + cheat_dist {
+                            counts[d2 - d - cheat_dist] += 1;
+                        }
+                    }
+                }
+                counts
+            },
+        )
+        .reduce(
+            || vec![0usize; track.len()],
+            |mut counts, other| {
+                counts
+                    .iter_mut()
+                    .zip(other)
+                    .for_each(|(count, other)| *count += other);
+                counts
+            },
+        );
+
+    counts
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, count)| count > 0)
+        .collect()
 }
 
 fn count_cheats(
-    field: &Vec<Vec<char>>,
+    field: &[Vec<char>],
     start: (usize, usize),
-    end: (usize, usize),
-    cheat_threshold: usize,
-    l1_distance_threshold: usize,
+    min_saving: usize,
+    max_cheat: usize,
 ) -> usize {
-    let path = calculate_path(field, start, end);
-
-    let threshold = path.len() - 1 - cheat_threshold;
-
-    (0..path.len())
-        .into_par_iter()
-        .map(|idx| {
-            let mut count = 0;
-            let remaining_dist = path.len() - idx - 1;
-
-            for idx2 in 0..idx {
-                let cheat_dist = l1_distance(path[idx2], path[idx]);
-                if cheat_dist <= l1_distance_threshold {
-                    let steps = remaining_dist + cheat_dist + idx2;
-                    if steps <= threshold {
-                        count += 1;
-                    }
-                }
-            }
-
-            count
-        })
-        .sum::<usize>()
+    cheat_histogram(field, start, max_cheat)
+        .range(min_saving..)
+        .map(|(_, count)| count)
+        .sum()
 }
 
 fn main() -> std::io::Result<()> {
@@ -89,30 +125,44 @@ fn main() -> std::io::Result<()> {
     let data = read_data("input.txt")?;
 
     let mut start = None;
-    let mut end = None;
-    for row in 0..data.len() {
-        for col in 0..data[row].len() {
-            if data[row][col] == 'S' {
+    for (row, line) in data.iter().enumerate() {
+        for (col, &c) in line.iter().enumerate() {
+            if c == 'S' {
                 start = Some((row, col));
             }
-
-            if data[row][col] == 'E' {
-                end = Some((row, col));
-            }
         }
     }
     let start = start.unwrap();
-    let end = end.unwrap();
 
     let start_time = std::time::Instant::now();
-    let task1 = count_cheats(&data, start, end, 100, 2);
+    let task1 = count_cheats(&data, start, 100, 2);
     let duration = start_time.elapsed();
     println!("Task 1: {task1} (took {:?})", duration);
 
     let start_time = std::time::Instant::now();
-    let task2 = count_cheats(&data, start, end, 100, 20);
+    let task2 = count_cheats(&data, start, 100, 20);
     let duration = start_time.elapsed();
     println!("Task 2: {task2} (took {:?})", duration);
 
+    // `cargo run -- histogram <max_cheat> [min_saving]` lists the cheats per
+    // saving like the tables in the puzzle text.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {}
+        ["histogram", max_cheat, ref rest @ ..] => {
+            let max_cheat = max_cheat.parse().expect("Invalid cheat length");
+            let min_saving = rest
+                .first()
+                .map_or(1, |min| min.parse().expect("Invalid saving"));
+            for (saving, count) in cheat_histogram(&data, start, max_cheat).range(min_saving..) {
+                match count {
+                    1 => println!("There is one cheat that saves {saving} picoseconds."),
+                    _ => println!("There are {count} cheats that save {saving} picoseconds."),
+                }
+            }
+        }
+        _ => panic!("Expected `histogram <max_cheat> [min_saving]`"),
+    }
+
     Ok(())
 }